@@ -15,12 +15,18 @@ pub enum AssetError {
     MessagePackEncodingError(#[from] rmp_serde::encode::Error),
     #[error(transparent)]
     YamlError(#[from] serde_yaml::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+    #[error(transparent)]
+    BincodeError(#[from] bincode::Error),
 }
 
 #[derive(Debug)]
 pub enum Backend {
     MessagePack,
     Yaml,
+    Json,
+    Binary,
 }
 
 pub trait Asset: DeserializeOwned + Serialize + Sized {
@@ -33,6 +39,8 @@ pub trait Asset: DeserializeOwned + Serialize + Sized {
         Ok(match Self::BACKEND {
             Backend::MessagePack => rmp_serde::from_read(reader)?,
             Backend::Yaml => serde_yaml::from_reader(reader)?,
+            Backend::Json => serde_json::from_reader(reader)?,
+            Backend::Binary => bincode::deserialize_from(reader)?,
         })
     }
 
@@ -43,8 +51,54 @@ pub trait Asset: DeserializeOwned + Serialize + Sized {
         match Self::BACKEND {
             Backend::MessagePack => rmp_serde::encode::write(&mut writer, self)?,
             Backend::Yaml => serde_yaml::to_writer(&mut writer, self)?,
+            Backend::Json => serde_json::to_writer(&mut writer, self)?,
+            Backend::Binary => bincode::serialize_into(&mut writer, self)?,
         }
 
         Ok(())
     }
 }
+
+/// Watches a loaded asset's on-disk path in debug builds and reloads it
+/// whenever the file's mtime changes, pushing the refreshed value back to
+/// the owner through a channel. Lets designers tweak `State` and other
+/// serialized assets live while the engine is running.
+#[cfg(debug_assertions)]
+pub struct AssetWatcher {
+    _handle: std::thread::JoinHandle<()>,
+}
+
+#[cfg(debug_assertions)]
+impl AssetWatcher {
+    pub fn watch<T: Asset + Send + 'static>(
+        path: impl AsRef<Path> + Send + 'static,
+        tx: std::sync::mpsc::Sender<T>,
+    ) -> Self {
+        let handle = std::thread::spawn(move || {
+            let mut last_modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+
+            loop {
+                std::thread::sleep(std::time::Duration::from_millis(250));
+
+                let Ok(modified) = std::fs::metadata(&path).and_then(|m| m.modified()) else {
+                    continue;
+                };
+                if Some(modified) == last_modified {
+                    continue;
+                }
+                last_modified = Some(modified);
+
+                match T::load(&path) {
+                    Ok(value) => {
+                        if tx.send(value).is_err() {
+                            return;
+                        }
+                    }
+                    Err(err) => tracing::error!(%err, path = %path.as_ref().display(), "failed to reload hot-reloaded asset"),
+                }
+            }
+        });
+
+        Self { _handle: handle }
+    }
+}
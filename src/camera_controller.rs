@@ -0,0 +1,253 @@
+use std::f32::consts::FRAC_PI_2;
+
+use glam::{Mat3, Vec3};
+use winit::event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::keyboard::{KeyCode, PhysicalKey};
+
+use crate::camera::Camera;
+
+const PITCH_LIMIT: f32 = FRAC_PI_2 - 0.01;
+
+/// An arcball-style controller: drag to orbit `target`, scroll to dolly
+/// along the view direction, middle-drag to pan `target` in the camera plane.
+pub struct OrbitController {
+    pub rotate_sensitivity: f32,
+    pub pan_sensitivity: f32,
+    pub zoom_sensitivity: f32,
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+    rotating: bool,
+    panning: bool,
+}
+
+impl OrbitController {
+    pub fn new(camera: &Camera) -> Self {
+        let offset = camera.position - camera.target;
+        let distance = offset.length();
+        Self {
+            rotate_sensitivity: 0.005,
+            pan_sensitivity: 0.0025,
+            zoom_sensitivity: 0.5,
+            // Yaw/pitch of the view direction (target - position), which is
+            // what `camera_basis` expects, so `apply` keeps the camera on its side of `target`.
+            yaw: (-offset.x).atan2(-offset.z),
+            pitch: (-offset.y / distance.max(f32::EPSILON)).asin(),
+            distance,
+            rotating: false,
+            panning: false,
+        }
+    }
+
+    pub fn handle_window_event(&mut self, event: &WindowEvent, camera: &mut Camera) {
+        match event {
+            WindowEvent::MouseInput { state, button, .. } => {
+                let pressed = *state == ElementState::Pressed;
+                match button {
+                    MouseButton::Left => self.rotating = pressed,
+                    MouseButton::Middle => self.panning = pressed,
+                    _ => (),
+                }
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let scroll = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / 100.0,
+                };
+                self.distance = (self.distance - scroll * self.zoom_sensitivity).max(0.1);
+                self.apply(camera);
+            }
+            _ => (),
+        }
+    }
+
+    /// Feeds raw, unaccumulated mouse motion (e.g. from `DeviceEvent::MouseMotion`).
+    pub fn handle_mouse_motion(&mut self, delta: (f64, f64), camera: &mut Camera) {
+        if self.rotating {
+            self.yaw -= delta.0 as f32 * self.rotate_sensitivity;
+            self.pitch = (self.pitch - delta.1 as f32 * self.rotate_sensitivity)
+                .clamp(-PITCH_LIMIT, PITCH_LIMIT);
+            self.apply(camera);
+        } else if self.panning {
+            let basis = camera_basis(self.yaw, self.pitch);
+            let pan = basis * Vec3::new(-delta.0 as f32, delta.1 as f32, 0.0) * self.pan_sensitivity;
+            camera.target += pan;
+            self.apply(camera);
+        }
+    }
+
+    fn apply(&self, camera: &mut Camera) {
+        let basis = camera_basis(self.yaw, self.pitch);
+        camera.position = camera.target + basis.z_axis * self.distance;
+    }
+}
+
+/// WASD translation in the camera basis with mouse-look yaw/pitch.
+pub struct FlyController {
+    pub move_speed: f32,
+    pub look_sensitivity: f32,
+    yaw: f32,
+    pitch: f32,
+    looking: bool,
+    forward: bool,
+    back: bool,
+    left: bool,
+    right: bool,
+    up: bool,
+    down: bool,
+}
+
+impl FlyController {
+    pub fn new(camera: &Camera) -> Self {
+        let forward = (camera.target - camera.position).normalize_or_zero();
+        Self {
+            move_speed: 4.0,
+            look_sensitivity: 0.005,
+            yaw: forward.x.atan2(forward.z),
+            pitch: forward.y.asin(),
+            looking: false,
+            forward: false,
+            back: false,
+            left: false,
+            right: false,
+            up: false,
+            down: false,
+        }
+    }
+
+    pub fn handle_window_event(&mut self, event: &WindowEvent, camera: &mut Camera) {
+        match event {
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Right,
+                ..
+            } => self.looking = *state == ElementState::Pressed,
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(code),
+                        state,
+                        ..
+                    },
+                ..
+            } => {
+                let pressed = *state == ElementState::Pressed;
+                match code {
+                    KeyCode::KeyW => self.forward = pressed,
+                    KeyCode::KeyS => self.back = pressed,
+                    KeyCode::KeyA => self.left = pressed,
+                    KeyCode::KeyD => self.right = pressed,
+                    KeyCode::Space => self.up = pressed,
+                    KeyCode::ShiftLeft => self.down = pressed,
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+
+        let _ = camera;
+    }
+
+    /// Feeds raw, unaccumulated mouse motion (e.g. from `DeviceEvent::MouseMotion`).
+    pub fn handle_mouse_motion(&mut self, delta: (f64, f64), camera: &mut Camera) {
+        if self.looking {
+            self.yaw -= delta.0 as f32 * self.look_sensitivity;
+            self.pitch = (self.pitch - delta.1 as f32 * self.look_sensitivity)
+                .clamp(-PITCH_LIMIT, PITCH_LIMIT);
+            self.apply(camera);
+        }
+    }
+
+    pub fn update(&mut self, dt: f32, camera: &mut Camera) {
+        let basis = camera_basis(self.yaw, self.pitch);
+        let mut translation = Vec3::ZERO;
+        if self.forward {
+            translation -= basis.z_axis;
+        }
+        if self.back {
+            translation += basis.z_axis;
+        }
+        if self.right {
+            translation += basis.x_axis;
+        }
+        if self.left {
+            translation -= basis.x_axis;
+        }
+        if self.up {
+            translation += Vec3::Y;
+        }
+        if self.down {
+            translation -= Vec3::Y;
+        }
+
+        if translation != Vec3::ZERO {
+            camera.position += translation.normalize() * self.move_speed * dt;
+        }
+        self.apply(camera);
+    }
+
+    fn apply(&self, camera: &mut Camera) {
+        let basis = camera_basis(self.yaw, self.pitch);
+        camera.target = camera.position - basis.z_axis;
+    }
+}
+
+/// Right-handed camera basis (x = right, y = up, z = backward) for the given yaw/pitch.
+fn camera_basis(yaw: f32, pitch: f32) -> Mat3 {
+    let forward = Vec3::new(
+        yaw.sin() * pitch.cos(),
+        pitch.sin(),
+        yaw.cos() * pitch.cos(),
+    );
+    let backward = -forward;
+
+    let right = Vec3::Y.cross(backward).normalize_or_zero();
+    let up = backward.cross(right).normalize_or_zero();
+
+    Mat3::from_cols(right, up, backward)
+}
+
+/// Which camera controller to construct, resolved once at startup from the
+/// `--camera` CLI flag.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CameraKind {
+    #[default]
+    Orbit,
+    Fly,
+}
+
+pub enum CameraController {
+    Orbit(OrbitController),
+    Fly(FlyController),
+}
+
+impl CameraController {
+    pub fn new(kind: CameraKind, camera: &Camera) -> Self {
+        match kind {
+            CameraKind::Orbit => Self::Orbit(OrbitController::new(camera)),
+            CameraKind::Fly => Self::Fly(FlyController::new(camera)),
+        }
+    }
+}
+
+impl CameraController {
+    pub fn handle_window_event(&mut self, event: &WindowEvent, camera: &mut Camera) {
+        match self {
+            Self::Orbit(controller) => controller.handle_window_event(event, camera),
+            Self::Fly(controller) => controller.handle_window_event(event, camera),
+        }
+    }
+
+    pub fn handle_mouse_motion(&mut self, delta: (f64, f64), camera: &mut Camera) {
+        match self {
+            Self::Orbit(controller) => controller.handle_mouse_motion(delta, camera),
+            Self::Fly(controller) => controller.handle_mouse_motion(delta, camera),
+        }
+    }
+
+    pub fn update(&mut self, dt: f32, camera: &mut Camera) {
+        if let Self::Fly(controller) = self {
+            controller.update(dt, camera);
+        }
+    }
+}
@@ -0,0 +1,242 @@
+//! GPU compute helpers for the `GraphicsContext` backend. `ParticleSystem`
+//! is driven by `WgpuRenderer`, which dispatches `step` and then draws its
+//! buffer with `particle_draw_pipeline` in the same frame's forward pass.
+
+use std::mem::size_of;
+
+use glam::Mat4;
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    vertex_attr_array, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, BlendState, Buffer, BufferAddress,
+    BufferBindingType, ColorTargetState, ColorWrites, CommandEncoder, CompareFunction, ComputePassDescriptor,
+    ComputePipeline, ComputePipelineDescriptor, DepthBiasState, DepthStencilState, FragmentState, FrontFace,
+    MultisampleState, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology,
+    PushConstantRange, RenderPipeline, RenderPipelineDescriptor, ShaderStages, StencilState, TextureFormat,
+    VertexBufferLayout, VertexState, VertexStepMode,
+};
+
+use crate::graphics_context::GraphicsContext;
+
+/// Builds a compute pipeline from a WGSL module and the bind-group layouts
+/// it reads/writes through. `push_constant_size` of 0 omits the push
+/// constant range entirely.
+pub fn compute_pipeline(
+    ctx: &GraphicsContext,
+    label: &str,
+    shader_source: wgpu::ShaderModuleDescriptor,
+    entry_point: &str,
+    bind_group_layouts: &[&BindGroupLayout],
+    push_constant_size: u32,
+) -> ComputePipeline {
+    let shader_module = ctx.device.create_shader_module(shader_source);
+
+    let push_constant_ranges = [PushConstantRange {
+        stages: ShaderStages::COMPUTE,
+        range: 0..push_constant_size,
+    }];
+
+    let pipeline_layout = ctx.device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts,
+        push_constant_ranges: if push_constant_size == 0 {
+            &[]
+        } else {
+            &push_constant_ranges
+        },
+    });
+
+    ctx.device.create_compute_pipeline(&ComputePipelineDescriptor {
+        label: Some(label),
+        layout: Some(&pipeline_layout),
+        module: &shader_module,
+        entry_point,
+        compilation_options: Default::default(),
+        cache: None,
+    })
+}
+
+/// Encodes a single compute pass dispatching `pipeline` over `workgroup_count`
+/// workgroups, binding `bind_groups` at consecutive group indices starting
+/// at 0 and writing `push_constants` before the dispatch, if any.
+///
+/// Any storage buffer written here and read by a later render pass within
+/// the same encoder is synchronized automatically by wgpu; no manual
+/// barrier is needed.
+pub fn dispatch(
+    encoder: &mut CommandEncoder,
+    label: &str,
+    pipeline: &ComputePipeline,
+    bind_groups: &[&BindGroup],
+    push_constants: Option<&[u8]>,
+    workgroup_count: [u32; 3],
+) {
+    let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+        label: Some(label),
+        timestamp_writes: None,
+    });
+
+    pass.set_pipeline(pipeline);
+    for (index, bind_group) in bind_groups.iter().enumerate() {
+        pass.set_bind_group(index as u32, bind_group, &[]);
+    }
+    if let Some(data) = push_constants {
+        pass.set_push_constants(0, data);
+    }
+
+    pass.dispatch_workgroups(workgroup_count[0], workgroup_count[1], workgroup_count[2]);
+}
+
+/// One particle's simulated state. `position`/`velocity` are advanced by the
+/// compute stage each frame; the render pass binds this same buffer as a
+/// vertex buffer (one instance per particle) to draw the results, so the
+/// simulation never leaves the GPU.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Particle {
+    pub position: [f32; 3],
+    pub velocity: [f32; 3],
+}
+
+/// A worked example of GPU-driven particle simulation: `step` dispatches a
+/// compute pass that advances every particle's position in `buffer`, which
+/// doubles as the vertex buffer a render pass draws from afterwards.
+pub struct ParticleSystem {
+    pub buffer: Buffer,
+    pub bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+    pipeline: ComputePipeline,
+    particle_count: u32,
+}
+
+impl ParticleSystem {
+    pub fn new(ctx: &GraphicsContext, particles: &[Particle]) -> Self {
+        let buffer = ctx.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("particle_buffer"),
+            contents: bytemuck::cast_slice(particles),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = ctx.device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("particle_bind_group_layout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::COMPUTE,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let bind_group = ctx.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("particle_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        let pipeline = compute_pipeline(
+            ctx,
+            "particle_pipeline",
+            wgpu::include_wgsl!("assets/particles.wgsl"),
+            "cs_main",
+            &[&bind_group_layout],
+            size_of::<f32>() as u32,
+        );
+
+        Self {
+            buffer,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+            particle_count: particles.len() as u32,
+        }
+    }
+
+    /// Advances every particle's position by `dt` seconds. The workgroup
+    /// count is rounded up to cover a trailing partial group of 64; the
+    /// shader bounds-checks `id.x` against `arrayLength` to ignore the
+    /// excess invocations that implies.
+    pub fn step(&self, encoder: &mut CommandEncoder, dt: f32) {
+        dispatch(
+            encoder,
+            "particle_step",
+            &self.pipeline,
+            &[&self.bind_group],
+            Some(bytemuck::bytes_of(&dt)),
+            [self.particle_count.div_ceil(64), 1, 1],
+        );
+    }
+
+    pub fn particle_count(&self) -> u32 {
+        self.particle_count
+    }
+}
+
+/// Builds the render pipeline that draws a `ParticleSystem`'s buffer as
+/// point geometry, reading `Particle::position` (stride covers the trailing
+/// `velocity` field too) as an per-instance vertex attribute. See
+/// `assets/particle_draw.wgsl`.
+pub fn particle_draw_pipeline(ctx: &GraphicsContext) -> RenderPipeline {
+    let shader_module = ctx
+        .device
+        .create_shader_module(wgpu::include_wgsl!("assets/particle_draw.wgsl"));
+
+    let pipeline_layout = ctx.device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("particle_draw_pipeline_layout"),
+        bind_group_layouts: &[],
+        push_constant_ranges: &[PushConstantRange {
+            stages: ShaderStages::VERTEX,
+            range: 0..size_of::<Mat4>() as u32,
+        }],
+    });
+
+    ctx.device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("particle_draw_pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: VertexState {
+            module: &shader_module,
+            entry_point: "vs_main",
+            buffers: &[VertexBufferLayout {
+                array_stride: size_of::<Particle>() as BufferAddress,
+                step_mode: VertexStepMode::Instance,
+                attributes: &vertex_attr_array![0 => Float32x3],
+            }],
+        },
+        primitive: PrimitiveState {
+            topology: PrimitiveTopology::PointList,
+            strip_index_format: None,
+            front_face: FrontFace::Ccw,
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: Some(DepthStencilState {
+            format: TextureFormat::Depth32Float,
+            depth_write_enabled: false,
+            depth_compare: CompareFunction::GreaterEqual,
+            stencil: StencilState::default(),
+            bias: DepthBiasState::default(),
+        }),
+        multisample: MultisampleState {
+            count: ctx.sample_count,
+            ..Default::default()
+        },
+        fragment: Some(FragmentState {
+            module: &shader_module,
+            entry_point: "fs_main",
+            targets: &[Some(ColorTargetState {
+                format: ctx.surface_config.format,
+                blend: Some(BlendState::REPLACE),
+                write_mask: ColorWrites::all(),
+            })],
+        }),
+        multiview: None,
+    })
+}
@@ -1,25 +1,53 @@
 use std::{
+    collections::VecDeque,
     f32::consts::TAU,
+    mem::size_of,
     net::{SocketAddr, UdpSocket},
     sync::{mpsc::Receiver, Arc},
-    time::Instant,
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
 
 use glam::{vec3, Mat4, Quat, Vec3};
 use serde::{Deserialize, Serialize};
-use tracing::error;
+use tracing::{error, warn};
 use winit::{
     dpi::LogicalSize,
-    event::{Event, WindowEvent},
+    event::{DeviceEvent, ElementState, Event, KeyEvent, WindowEvent},
     event_loop::EventLoop,
+    keyboard::{KeyCode, PhysicalKey},
     window::{Window, WindowBuilder},
 };
 
-use crate::{camera::Camera, graphics::GraphicsSubsystem};
+use crate::{
+    asset::Asset,
+    camera::Camera,
+    camera_controller::{CameraController, CameraKind},
+    graphics::GraphicsSubsystem,
+    ray_pipeline::RendererKind,
+    state::State,
+    wgpu_renderer::WgpuRenderer,
+};
+#[cfg(debug_assertions)]
+use crate::asset::AssetWatcher;
 
 const WINDOW_SIZE: LogicalSize<u32> = LogicalSize::new(1280, 720);
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+/// Where designer-tunable `State` is loaded from (and, in debug builds,
+/// hot-reloaded from whenever it's edited on disk).
+const STATE_PATH: &str = "assets/state.yaml";
+
+/// Bytes reserved for the sequence number and tick timestamp at the start of
+/// every snapshot datagram, ahead of the MessagePack-encoded payload.
+const SNAPSHOT_HEADER_SIZE: usize = size_of::<u32>() + size_of::<u64>();
+/// Large enough for a MessagePack-encoded `SynchronizedState` plus header;
+/// oversized/fragmented datagrams are truncated by `recv` and rejected.
+const SNAPSHOT_RECV_BUFFER_SIZE: usize = 4096;
+/// How far behind the newest received tick the render clock trails, so
+/// there's always a second snapshot on hand to interpolate towards despite
+/// jitter and loss.
+const RENDER_DELAY_MILLIS: u64 = 100;
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
 pub struct SynchronizedState {
     player_transforms: [Mat4; 4],
 }
@@ -28,20 +56,78 @@ impl SynchronizedState {
     fn new() -> Self {
         Self::default()
     }
+
+    /// Linearly interpolates translation and scale and spherically
+    /// interpolates rotation between two states, per transform.
+    fn interpolate(a: &Self, b: &Self, t: f32) -> Self {
+        let mut out = Self::default();
+        for i in 0..out.player_transforms.len() {
+            let (scale_a, rotation_a, translation_a) = a.player_transforms[i].to_scale_rotation_translation();
+            let (scale_b, rotation_b, translation_b) = b.player_transforms[i].to_scale_rotation_translation();
+
+            out.player_transforms[i] = Mat4::from_scale_rotation_translation(
+                scale_a.lerp(scale_b, t),
+                rotation_a.slerp(rotation_b, t),
+                translation_a.lerp(translation_b, t),
+            );
+        }
+        out
+    }
+}
+
+/// A decoded, sequenced `SynchronizedState` update received from the network.
+#[derive(Debug, Clone)]
+struct Snapshot {
+    sequence: u32,
+    tick_millis: u64,
+    state: SynchronizedState,
+}
+
+/// Which graphics backend to render through, resolved once at startup from
+/// the `--backend` CLI flag. Only one is ever constructed: running both
+/// would mean two graphics APIs contending for the same window's swapchain.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    #[default]
+    Vulkano,
+    Wgpu,
+}
+
+enum Renderer {
+    Vulkano(GraphicsSubsystem),
+    Wgpu(WgpuRenderer),
 }
 
 pub struct Engine {
     event_loop: EventLoop<()>,
     window: Arc<Window>,
     scale_factor: f64,
-    graphics: GraphicsSubsystem,
+    renderer_backend: Renderer,
     camera: Camera,
+    camera_controller: CameraController,
+    /// The render path actually in use, which may have fallen back to
+    /// `Raster` if the adapter lacked the required ray tracing features.
+    renderer: RendererKind,
+    /// Designer-tunable state, loaded from `STATE_PATH` and, in debug
+    /// builds, live-reloaded whenever `state_watcher` notices the file change.
+    state: State,
+    #[cfg(debug_assertions)]
+    state_watcher: AssetWatcher,
+    #[cfg(debug_assertions)]
+    incoming_state: Receiver<State>,
     sync_state: SynchronizedState,
-    incoming_state: Receiver<SynchronizedState>,
+    /// The two most recent snapshots, oldest first, used to interpolate `sync_state`.
+    recent_snapshots: VecDeque<Snapshot>,
+    incoming_snapshots: Receiver<Snapshot>,
 }
 
 impl Engine {
-    pub fn new(address: Option<SocketAddr>) -> anyhow::Result<Self> {
+    pub fn new(
+        address: Option<SocketAddr>,
+        renderer: RendererKind,
+        camera_kind: CameraKind,
+        backend: Backend,
+    ) -> anyhow::Result<Self> {
         let event_loop = EventLoop::new()?;
         let window = Arc::new(
             WindowBuilder::new()
@@ -49,7 +135,28 @@ impl Engine {
                 .build(&event_loop)?,
         );
         let scale_factor = window.scale_factor();
-        let graphics = GraphicsSubsystem::new(&event_loop, window.clone())?;
+
+        // Ray tracing only exists on the wgpu backend (`ray_pipeline` builds directly on
+        // `GraphicsContext`'s adapter); the vulkano backend has no ray-traced path at all, so
+        // a ray-traced request always falls back there regardless of adapter support.
+        let (renderer_backend, renderer) = match backend {
+            Backend::Vulkano => {
+                let graphics = GraphicsSubsystem::new(&event_loop, window.clone())?;
+                let renderer = match renderer {
+                    RendererKind::Raster => RendererKind::Raster,
+                    RendererKind::RayTraced => {
+                        warn!("ray-traced renderer requested but the vulkano backend has no ray-traced path; falling back to raster");
+                        RendererKind::Raster
+                    }
+                };
+                (Renderer::Vulkano(graphics), renderer)
+            }
+            Backend::Wgpu => {
+                let wgpu_renderer = WgpuRenderer::new(window.clone(), window.inner_size(), renderer)?;
+                let renderer = wgpu_renderer.active_renderer();
+                (Renderer::Wgpu(wgpu_renderer), renderer)
+            }
+        };
 
         let camera = Camera {
             position: Vec3::new(-1.0, 1.0, -1.0),
@@ -57,6 +164,14 @@ impl Engine {
             fov: 80_f32.to_radians(),
             near: 0.01,
         };
+        let camera_controller = CameraController::new(camera_kind, &camera);
+
+        let state = State::load(STATE_PATH).unwrap_or_default();
+        #[cfg(debug_assertions)]
+        let (state_watcher, incoming_state) = {
+            let (state_tx, state_rx) = std::sync::mpsc::channel();
+            (AssetWatcher::watch(STATE_PATH, state_tx), state_rx)
+        };
 
         let sync_state = SynchronizedState::new();
 
@@ -67,14 +182,42 @@ impl Engine {
         }
 
         std::thread::spawn(move || {
-            // messages sometimes get segmented
-            // messages don't always get to the receiver
-            // messages don't always come in the same order that were sent
-
-            let mut bytes = vec![];
-            while let Ok(bytes_read) = socket.recv(&mut bytes) {
-                if bytes.len() >= std::mem::size_of::<SynchronizedState>() {
-                    // tx.send(rmp_serde:: SynchronizedState::)
+            // UDP datagrams can arrive dropped, reordered, or (rarely) duplicated, so every
+            // datagram is prefixed with a monotonically increasing sequence number: anything
+            // older than the last one applied is stale and gets dropped on the floor.
+            let mut recv_buffer = [0u8; SNAPSHOT_RECV_BUFFER_SIZE];
+            let mut last_sequence = None;
+
+            while let Ok(bytes_read) = socket.recv(&mut recv_buffer) {
+                if bytes_read < SNAPSHOT_HEADER_SIZE {
+                    continue;
+                }
+
+                let sequence = u32::from_be_bytes(recv_buffer[0..4].try_into().unwrap());
+                let tick_millis = u64::from_be_bytes(recv_buffer[4..SNAPSHOT_HEADER_SIZE].try_into().unwrap());
+
+                if last_sequence.is_some_and(|last| sequence <= last) {
+                    continue;
+                }
+                last_sequence = Some(sequence);
+
+                let state = match rmp_serde::from_slice(&recv_buffer[SNAPSHOT_HEADER_SIZE..bytes_read]) {
+                    Ok(state) => state,
+                    Err(err) => {
+                        error!(%err, "failed to decode synchronized state snapshot");
+                        continue;
+                    }
+                };
+
+                if tx
+                    .send(Snapshot {
+                        sequence,
+                        tick_millis,
+                        state,
+                    })
+                    .is_err()
+                {
+                    return;
                 }
             }
         });
@@ -83,92 +226,135 @@ impl Engine {
             event_loop,
             window,
             scale_factor,
-            graphics,
+            renderer_backend,
             camera,
+            camera_controller,
+            renderer,
+            state,
+            #[cfg(debug_assertions)]
+            state_watcher,
+            #[cfg(debug_assertions)]
+            incoming_state,
             sync_state,
-            incoming_state: rx,
+            recent_snapshots: VecDeque::with_capacity(2),
+            incoming_snapshots: rx,
         })
     }
 
     pub fn run(mut self) -> anyhow::Result<()> {
         let start = Instant::now();
+        let mut last_frame = start;
+        let mut last_render = start;
         self.event_loop.run(|event, elwt| match event {
             Event::WindowEvent { window_id, event } => {
                 if window_id == self.window.id() {
+                    self.camera_controller.handle_window_event(&event, &mut self.camera);
+
                     match event {
-                        WindowEvent::Resized(new_size) => {
-                            if let Err(err) = self.graphics.resize_window(new_size) {
-                                error!(%err, "Failed to resize the window");
-                                elwt.exit();
+                        WindowEvent::Resized(new_size) => match &mut self.renderer_backend {
+                            Renderer::Vulkano(graphics) => {
+                                if let Err(err) = graphics.resize_window(new_size) {
+                                    error!(%err, "Failed to resize the window");
+                                    elwt.exit();
+                                }
                             }
-                        }
+                            Renderer::Wgpu(wgpu_renderer) => wgpu_renderer.resize(new_size),
+                        },
                         WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
                             self.scale_factor = scale_factor;
                         }
                         WindowEvent::CloseRequested => elwt.exit(),
                         WindowEvent::Destroyed => (),
+                        WindowEvent::KeyboardInput {
+                            event:
+                                KeyEvent {
+                                    physical_key: PhysicalKey::Code(KeyCode::F12),
+                                    state: ElementState::Pressed,
+                                    repeat: false,
+                                    ..
+                                },
+                            ..
+                        } => {
+                            // Screenshots only exist on the wgpu backend: vulkano's
+                            // `GraphicsSubsystem` presents from its own render thread and has
+                            // no equivalent read-back path wired up.
+                            if let Renderer::Wgpu(wgpu_renderer) = &mut self.renderer_backend {
+                                let timestamp = SystemTime::now()
+                                    .duration_since(UNIX_EPOCH)
+                                    .unwrap_or_default()
+                                    .as_secs();
+                                wgpu_renderer.request_screenshot(format!("screenshot-{timestamp}.png"));
+                            }
+                        }
                         WindowEvent::RedrawRequested => {
-                            // if let Some((surface, view)) = self.gfx.get_frame() {
-                            //     let mut encoder = self.gfx.device.create_command_encoder(
-                            //         &CommandEncoderDescriptor {
-                            //             label: Some("encoder"),
-                            //         },
-                            //     );
-
-                            //     let mut render_pass =
-                            //         encoder.begin_render_pass(&RenderPassDescriptor {
-                            //             label: None,
-                            //             color_attachments: &[Some(RenderPassColorAttachment {
-                            //                 view: &view,
-                            //                 resolve_target: None,
-                            //                 ops: Operations {
-                            //                     load: LoadOp::Clear(Color::BLACK),
-                            //                     store: StoreOp::Store,
-                            //                 },
-                            //             })],
-                            //             depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
-                            //                 view: &self.gfx.depth_texture_view,
-                            //                 depth_ops: Some(Operations {
-                            //                     load: LoadOp::Clear(0.0),
-                            //                     store: StoreOp::Store,
-                            //                 }),
-                            //                 stencil_ops: None,
-                            //             }),
-                            //             timestamp_writes: None,
-                            //             occlusion_query_set: None,
-                            //         });
-
-                            //     render_pass.set_pipeline(&self.mesh_pipeline);
-                            //     render_pass.set_vertex_buffer(0, self.turtle.positions.slice(..));
-                            //     render_pass.set_vertex_buffer(1, self.turtle.colors.slice(..));
-                            //     render_pass.set_index_buffer(self.turtle.indices.slice(..), IndexFormat::Uint16);
-
-                            //     for transform in self.sync_state.player_transforms {
-                            //         render_pass.set_push_constants(ShaderStages::VERTEX, 0, bytemuck::bytes_of(&PushConstants {
-                            //             mvp: self.camera.view_projection(self.window_state.aspect_ratio()) * transform,
-                            //         }));
-
-                            //         render_pass.draw_indexed(0..self.turtle.index_count, 0, 0..1);
-                            //     }
-
-                            //     drop(render_pass);
-
-                            //     self.gfx.submit([encoder.finish()]);
-                            //     self.window_state.window.pre_present_notify();
-                            //     surface.present();
-                            // }
+                            // The vulkano backend presents from its own render thread (see
+                            // `GraphicsSubsystem`) and needs nothing driven from here; the wgpu
+                            // backend renders synchronously on this thread instead.
+                            if let Renderer::Wgpu(wgpu_renderer) = &mut self.renderer_backend {
+                                let now = Instant::now();
+                                let dt = (now - last_render).as_secs_f32();
+                                last_render = now;
+
+                                let size = self.window.inner_size();
+                                let aspect_ratio = size.width as f32 / size.height.max(1) as f32;
+                                let view_projection = self.camera.view_projection(aspect_ratio);
+                                if let Err(err) = wgpu_renderer.render(&self.window, view_projection, dt) {
+                                    error!(%err, "Failed to render a frame");
+                                    elwt.exit();
+                                }
+                            }
                         }
                         _ => (),
                     }
                 }
             }
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta },
+                ..
+            } => {
+                self.camera_controller
+                    .handle_mouse_motion(delta, &mut self.camera);
+            }
             Event::AboutToWait => {
+                let now = Instant::now();
+                let dt = (now - last_frame).as_secs_f32();
+                last_frame = now;
+
+                // Keep only the newest hot-reloaded `State`; intermediate edits don't matter.
+                #[cfg(debug_assertions)]
+                while let Ok(state) = self.incoming_state.try_recv() {
+                    self.state = state;
+                }
+
+                while let Ok(snapshot) = self.incoming_snapshots.try_recv() {
+                    if self.recent_snapshots.len() == 2 {
+                        self.recent_snapshots.pop_front();
+                    }
+                    self.recent_snapshots.push_back(snapshot);
+                }
+
+                if let [older, newer] = self.recent_snapshots.make_contiguous() {
+                    let render_tick = newer.tick_millis.saturating_sub(RENDER_DELAY_MILLIS);
+                    let span = newer.tick_millis.saturating_sub(older.tick_millis).max(1);
+                    let t = (render_tick.saturating_sub(older.tick_millis) as f32 / span as f32).clamp(0.0, 1.0);
+
+                    self.sync_state = SynchronizedState::interpolate(&older.state, &newer.state, t);
+                }
+
                 let elapsed = start.elapsed().as_secs_f32();
                 self.sync_state.player_transforms[0] = Mat4::from_rotation_translation(
                     Quat::from_axis_angle(Vec3::Y, elapsed * 6.23 / TAU),
                     vec3(elapsed.sin(), 0.0, elapsed.cos()).normalize() * 3.0,
                 );
-                self.camera.position = vec3(0.2, 0.5, 0.2).normalize() * 8.0;
+
+                self.camera_controller.update(dt, &mut self.camera);
+
+                // The vulkano backend's render thread redraws on its own independent loop;
+                // the wgpu backend only ever renders in response to `RedrawRequested`, so it
+                // has to be asked for the next one explicitly to keep animating.
+                if let Renderer::Wgpu(_) = &self.renderer_backend {
+                    self.window.request_redraw();
+                }
             }
             _ => (),
         })?;
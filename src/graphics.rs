@@ -9,18 +9,28 @@ use std::{
 use anyhow::anyhow;
 use glam::Vec3;
 use vulkano::{
-    buffer::BufferContents,
-    command_buffer::allocator::StandardCommandBufferAllocator,
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, BlitImageInfo,
+        CommandBufferUsage, CopyBufferToImageInfo, ImageBlit, RenderPassBeginInfo,
+        SubpassBeginInfo, SubpassContents, SubpassEndInfo,
+    },
     device::{
         physical::{PhysicalDevice, PhysicalDeviceType},
         Device, DeviceCreateInfo, DeviceExtensions, Queue, QueueCreateInfo, QueueFlags,
     },
-    image::{view::ImageView, Image, ImageUsage},
+    format::Format,
+    image::{
+        sampler::{Filter, Sampler, SamplerCreateInfo, SamplerMipmapMode},
+        view::ImageView, Image, ImageCreateInfo, ImageSubresourceLayers, ImageType, ImageUsage,
+        SampleCount, SampleCounts,
+    },
     instance::{Instance, InstanceCreateFlags, InstanceCreateInfo, InstanceExtensions},
-    memory::allocator::StandardMemoryAllocator,
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
     pipeline::{
         graphics::{
             color_blend::{ColorBlendAttachmentState, ColorBlendState},
+            depth_stencil::{DepthState, DepthStencilState},
             input_assembly::InputAssemblyState,
             multisample::MultisampleState,
             rasterization::RasterizationState,
@@ -32,12 +42,24 @@ use vulkano::{
         DynamicState, GraphicsPipeline, PipelineLayout, PipelineShaderStageCreateInfo,
     },
     render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
-    swapchain::{CompositeAlpha, Surface, Swapchain, SwapchainCreateInfo},
-    Version, VulkanLibrary,
+    swapchain::{
+        acquire_next_image, CompositeAlpha, Surface, Swapchain, SwapchainCreateInfo,
+        SwapchainPresentInfo,
+    },
+    sync::{self, GpuFuture},
+    Validated, Version, VulkanError, VulkanLibrary,
 };
 use winit::{dpi::PhysicalSize, event_loop::EventLoop, window::Window};
 
 const EVENT_BUFFER_SIZE: usize = 16;
+/// The MSAA level requested of the adapter, akin to a "stage quality" setting
+/// a player might pick; `highest_supported_sample_count` clamps this down to
+/// whatever the physical device actually supports.
+const REQUESTED_MSAA_SAMPLES: u32 = 4;
+/// How many frames the CPU is allowed to queue up ahead of the GPU. Each
+/// ring slot's fence must signal before that slot's command buffer is
+/// recorded again, bounding overlap without forcing a full `device_wait_idle`.
+const MAX_FRAMES_IN_FLIGHT: usize = 2;
 
 enum GraphicsCommand {
     LoadModel(usize, &'static [u8]),
@@ -69,15 +91,14 @@ impl GraphicsSubsystem {
             match rx.try_recv() {
                 Ok(command) => match command {
                     GraphicsCommand::LoadModel(index, model) => todo!(),
-                    GraphicsCommand::LoadTexture(index, texture) => todo!(),
+                    GraphicsCommand::LoadTexture(index, texture) => ctx.load_texture(index, texture)?,
                     GraphicsCommand::ResizeSwapchain(new_size) => ctx.recreate_swapchain(new_size)?,
                 },
                 Err(TryRecvError::Disconnected) => return Ok(()),
                 Err(TryRecvError::Empty) => (),
             }
 
-            window.pre_present_notify();
-            // window.request_redraw();
+            ctx.render_frame(&window)?;
         });
 
         Ok(Self {
@@ -120,12 +141,32 @@ struct RenderContext {
     queue: Arc<Queue>,
     swapchain: Arc<Swapchain>,
     images: Vec<Arc<Image>>,
-    memory_allocator: StandardMemoryAllocator,
+    memory_allocator: Arc<StandardMemoryAllocator>,
     render_pass: Arc<RenderPass>,
+    /// The effective MSAA sample count, clamped to what the physical device
+    /// supports for both the color and depth attachments.
+    sample_count: SampleCount,
+    /// The depth target every framebuffer attaches, regardless of MSAA.
+    depth_target: Arc<ImageView>,
+    /// The multisampled color target the forward pass renders into and
+    /// resolves from each frame; `None` when `sample_count` is 1 and frames
+    /// render directly into the swapchain image.
+    msaa_color: Option<Arc<ImageView>>,
     pipeline: Arc<GraphicsPipeline>,
     viewport: Viewport,
     framebuffers: Vec<Arc<Framebuffer>>,
     command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    /// Placeholder geometry bound and drawn every frame until
+    /// `GraphicsCommand::LoadModel` can populate real per-model buffers.
+    vertex_buffer: Subbuffer<[Vert]>,
+    /// Uploaded textures indexed by `Texture(usize)` handle; holes for
+    /// handles whose `LoadTexture` command hasn't reached the render thread yet.
+    textures: Vec<Option<(Arc<ImageView>, Arc<Sampler>)>>,
+    /// A `MAX_FRAMES_IN_FLIGHT`-sized ring of the previous occupant's
+    /// "everything submitted for this slot has finished" future, indexed by
+    /// `frame_index % MAX_FRAMES_IN_FLIGHT`.
+    fences: Vec<Option<Box<dyn GpuFuture>>>,
+    frame_index: usize,
 }
 
 impl RenderContext {
@@ -218,23 +259,76 @@ impl RenderContext {
             )?
         };
 
-        let memory_allocator = StandardMemoryAllocator::new_default(device.clone());
+        let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
 
-        let render_pass = vulkano::single_pass_renderpass!(
-            device.clone(),
-            attachments: {
-                color: {
-                    format: swapchain.image_format(),
-                    samples: 1,
-                    load_op: Clear,
-                    store_op: Store,
+        let sample_count = highest_supported_sample_count(&physical_device, REQUESTED_MSAA_SAMPLES);
+
+        let (render_pass, msaa_color) = if sample_count == SampleCount::Sample1 {
+            let render_pass = vulkano::single_pass_renderpass!(
+                device.clone(),
+                attachments: {
+                    color: {
+                        format: swapchain.image_format(),
+                        samples: 1,
+                        load_op: Clear,
+                        store_op: Store,
+                    },
+                    depth: {
+                        format: Format::D32_SFLOAT,
+                        samples: 1,
+                        load_op: Clear,
+                        store_op: DontCare,
+                    },
                 },
-            },
-            pass: {
-                color: [color],
-                depth_stencil: {},
-            },
-        )?;
+                pass: {
+                    color: [color],
+                    depth_stencil: {depth},
+                },
+            )?;
+
+            (render_pass, None)
+        } else {
+            let render_pass = vulkano::single_pass_renderpass!(
+                device.clone(),
+                attachments: {
+                    msaa_color: {
+                        format: swapchain.image_format(),
+                        samples: sample_count,
+                        load_op: Clear,
+                        store_op: DontCare,
+                    },
+                    color: {
+                        format: swapchain.image_format(),
+                        samples: 1,
+                        load_op: DontCare,
+                        store_op: Store,
+                    },
+                    depth: {
+                        format: Format::D32_SFLOAT,
+                        samples: sample_count,
+                        load_op: Clear,
+                        store_op: DontCare,
+                    },
+                },
+                pass: {
+                    color: [msaa_color],
+                    color_resolve: [color],
+                    depth_stencil: {depth},
+                },
+            )?;
+
+            let msaa_color = create_msaa_color_target(
+                memory_allocator.clone(),
+                swapchain.image_format(),
+                swapchain_size,
+                sample_count,
+            )?;
+
+            (render_pass, Some(msaa_color))
+        };
+
+        let depth_target =
+            create_depth_target(memory_allocator.clone(), swapchain_size, sample_count)?;
 
         let pipeline = {
             let vs = vs::load(device.clone())?.entry_point("main").unwrap();
@@ -266,11 +360,18 @@ impl RenderContext {
                     input_assembly_state: Some(InputAssemblyState::default()),
                     viewport_state: Some(ViewportState::default()),
                     rasterization_state: Some(RasterizationState::default()),
-                    multisample_state: Some(MultisampleState::default()),
+                    multisample_state: Some(MultisampleState {
+                        rasterization_samples: sample_count,
+                        ..Default::default()
+                    }),
                     color_blend_state: Some(ColorBlendState::with_attachment_states(
                         subpass.num_color_attachments(),
                         ColorBlendAttachmentState::default(),
                     )),
+                    depth_stencil_state: Some(DepthStencilState {
+                        depth: Some(DepthState::simple()),
+                        ..Default::default()
+                    }),
                     dynamic_state: [DynamicState::Viewport].into_iter().collect(),
                     subpass: Some(subpass.into()),
                     ..GraphicsPipelineCreateInfo::layout(layout)
@@ -284,13 +385,48 @@ impl RenderContext {
             depth_range: 0.0..=1.0,
         };
 
-        let framebuffers = create_framebuffers(&images, render_pass.clone(), &mut viewport)?;
+        let framebuffers = create_framebuffers(
+            &images,
+            render_pass.clone(),
+            &mut viewport,
+            &depth_target,
+            msaa_color.as_ref(),
+        )?;
 
         let command_buffer_allocator = Arc::new(StandardCommandBufferAllocator::new(
             device.clone(),
             Default::default(),
         ));
 
+        // A hardcoded placeholder triangle, bound and drawn every frame until
+        // `GraphicsCommand::LoadModel` can upload real model geometry.
+        let vertex_buffer = Buffer::from_iter(
+            memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            [
+                Vert {
+                    position: Vec3::new(0.0, -0.5, 0.0),
+                    color: Vec3::new(1.0, 0.0, 0.0),
+                },
+                Vert {
+                    position: Vec3::new(0.5, 0.5, 0.0),
+                    color: Vec3::new(0.0, 1.0, 0.0),
+                },
+                Vert {
+                    position: Vec3::new(-0.5, 0.5, 0.0),
+                    color: Vec3::new(0.0, 0.0, 1.0),
+                },
+            ],
+        )?;
+
         Ok(Self {
             library,
             instance,
@@ -302,13 +438,199 @@ impl RenderContext {
             images,
             memory_allocator,
             render_pass,
+            sample_count,
+            depth_target,
+            msaa_color,
             pipeline,
             viewport,
             framebuffers,
             command_buffer_allocator,
+            vertex_buffer,
+            textures: Vec::new(),
+            fences: (0..MAX_FRAMES_IN_FLIGHT).map(|_| None).collect(),
+            frame_index: 0,
         })
     }
 
+    /// Decodes `bytes` into RGBA8, uploads it to a device-local, fully
+    /// mipmapped `Image` via a host-visible staging buffer, and stores the
+    /// resulting view/sampler pair at `index` in `self.textures`.
+    fn load_texture(&mut self, index: usize, bytes: &'static [u8]) -> anyhow::Result<()> {
+        let decoded = image::load_from_memory(bytes)?.to_rgba8();
+        let (width, height) = decoded.dimensions();
+        let mip_levels = (width.max(height) as f32).log2().floor() as u32 + 1;
+
+        let staging_buffer = Buffer::from_iter(
+            self.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            decoded.into_raw(),
+        )?;
+
+        let image = Image::new(
+            self.memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: Format::R8G8B8A8_UNORM,
+                extent: [width, height, 1],
+                mip_levels,
+                usage: ImageUsage::TRANSFER_DST | ImageUsage::TRANSFER_SRC | ImageUsage::SAMPLED,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )?;
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+
+        builder.copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(
+            staging_buffer,
+            image.clone(),
+        ))?;
+
+        // Sequential blits from each mip level into the next, halving the
+        // extent every step and clamping to a minimum of 1 so non-square
+        // textures bottom out on their shorter axis without hitting zero.
+        let mut src_extent = [width, height, 1];
+        for level in 1..mip_levels {
+            let dst_extent = [(src_extent[0] / 2).max(1), (src_extent[1] / 2).max(1), 1];
+
+            builder.blit_image(BlitImageInfo {
+                regions: [ImageBlit {
+                    src_subresource: ImageSubresourceLayers {
+                        mip_level: level - 1,
+                        ..image.subresource_layers()
+                    },
+                    src_offsets: [[0, 0, 0], src_extent],
+                    dst_subresource: ImageSubresourceLayers {
+                        mip_level: level,
+                        ..image.subresource_layers()
+                    },
+                    dst_offsets: [[0, 0, 0], dst_extent],
+                    ..Default::default()
+                }]
+                .into(),
+                filter: Filter::Linear,
+                ..BlitImageInfo::images(image.clone(), image.clone())
+            })?;
+
+            src_extent = dst_extent;
+        }
+
+        builder
+            .build()?
+            .execute(self.queue.clone())?
+            .then_signal_fence_and_flush()?
+            .wait(None)?;
+
+        let view = ImageView::new_default(image)?;
+        let sampler = Sampler::new(
+            self.device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                mipmap_mode: SamplerMipmapMode::Linear,
+                ..Default::default()
+            },
+        )?;
+
+        if self.textures.len() <= index {
+            self.textures.resize(index + 1, None);
+        }
+        self.textures[index] = Some((view, sampler));
+
+        Ok(())
+    }
+
+    /// Acquires the next swapchain image, records a render pass that clears,
+    /// draws `self.vertex_buffer` through `self.pipeline` (a placeholder
+    /// triangle until `GraphicsCommand::LoadModel` can upload real per-model
+    /// buffers), and submits it synchronized by the acquire future, a
+    /// render-finished present, and this frame's ring slot fence.
+    fn render_frame(&mut self, window: &Window) -> anyhow::Result<()> {
+        let ring_slot = self.frame_index % MAX_FRAMES_IN_FLIGHT;
+        if let Some(fence) = self.fences[ring_slot].take() {
+            fence.wait(None)?;
+        }
+
+        let (image_index, suboptimal, acquire_future) =
+            match acquire_next_image(self.swapchain.clone(), None) {
+                Ok(result) => result,
+                Err(Validated::Error(VulkanError::OutOfDate)) => {
+                    return self.recreate_swapchain(window.inner_size());
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+        let clear_values = if self.sample_count == SampleCount::Sample1 {
+            // Order matches the `color, depth` attachments declared in `new`.
+            vec![Some([0.0, 0.0, 0.0, 1.0].into()), Some(1.0.into())]
+        } else {
+            // Order matches the `msaa_color, color, depth` attachments declared in `new`.
+            vec![Some([0.0, 0.0, 0.0, 1.0].into()), None, Some(1.0.into())]
+        };
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values,
+                    ..RenderPassBeginInfo::framebuffer(self.framebuffers[image_index as usize].clone())
+                },
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )?
+            .bind_pipeline_graphics(self.pipeline.clone())?
+            .set_viewport(0, [self.viewport.clone()].into_iter().collect())?
+            .bind_vertex_buffers(0, self.vertex_buffer.clone())?
+            .draw(self.vertex_buffer.len() as u32, 1, 0, 0)?
+            .end_render_pass(SubpassEndInfo::default())?;
+
+        let command_buffer = builder.build()?;
+
+        window.pre_present_notify();
+
+        let future = sync::now(self.device.clone())
+            .join(acquire_future)
+            .then_execute(self.queue.clone(), command_buffer)?
+            .then_swapchain_present(
+                self.queue.clone(),
+                SwapchainPresentInfo::swapchain_image_index(self.swapchain.clone(), image_index),
+            )
+            .then_signal_fence_and_flush();
+
+        self.fences[ring_slot] = match future {
+            Ok(future) => Some(future.boxed()),
+            Err(Validated::Error(VulkanError::OutOfDate)) => None,
+            Err(err) => return Err(err.into()),
+        };
+
+        self.frame_index += 1;
+
+        if suboptimal {
+            self.recreate_swapchain(window.inner_size())?;
+        }
+
+        Ok(())
+    }
+
     fn recreate_swapchain(&mut self, new_size: PhysicalSize<u32>) -> anyhow::Result<()> {
         let (new_swapchain, new_images) = self.swapchain
         .recreate(SwapchainCreateInfo {
@@ -318,10 +640,24 @@ impl RenderContext {
 
         self.swapchain = new_swapchain;
 
+        self.depth_target =
+            create_depth_target(self.memory_allocator.clone(), new_size, self.sample_count)?;
+
+        if self.sample_count != SampleCount::Sample1 {
+            self.msaa_color = Some(create_msaa_color_target(
+                self.memory_allocator.clone(),
+                self.swapchain.image_format(),
+                new_size,
+                self.sample_count,
+            )?);
+        }
+
         self.framebuffers = create_framebuffers(
             &new_images,
             self.render_pass.clone(),
             &mut self.viewport,
+            &self.depth_target,
+            self.msaa_color.as_ref(),
         )?;
 
         Ok(())
@@ -369,21 +705,33 @@ mod fs {
 }
 
 /// This function is called once during initialization, then again whenever the window is resized.
+///
+/// `depth_target` is always attached. When `msaa_color` is `Some`, each
+/// framebuffer renders into the shared multisampled color target and
+/// resolves into the swapchain image; otherwise the swapchain image is the
+/// sole, directly-rendered color attachment.
 fn create_framebuffers(
     images: &[Arc<Image>],
     render_pass: Arc<RenderPass>,
     viewport: &mut Viewport,
+    depth_target: &Arc<ImageView>,
+    msaa_color: Option<&Arc<ImageView>>,
 ) -> anyhow::Result<Vec<Arc<Framebuffer>>> {
     let extent = images[0].extent();
     viewport.extent = [extent[0] as f32, extent[1] as f32];
 
     let mut framerbuffers = vec![];
     for image in images {
-        let view = ImageView::new_default(image.clone())?;
+        let resolve_view = ImageView::new_default(image.clone())?;
+        let attachments = match msaa_color {
+            Some(msaa_color) => vec![msaa_color.clone(), resolve_view, depth_target.clone()],
+            None => vec![resolve_view, depth_target.clone()],
+        };
+
         framerbuffers.push(Framebuffer::new(
             render_pass.clone(),
             FramebufferCreateInfo {
-                attachments: vec![view],
+                attachments,
                 ..Default::default()
             },
         )?)
@@ -391,3 +739,71 @@ fn create_framebuffers(
 
     Ok(framerbuffers)
 }
+
+/// Picks the highest sample count in `1, 2, 4, 8` that is both `<= requested`
+/// and supported by `physical_device` for both color and depth attachments.
+fn highest_supported_sample_count(physical_device: &PhysicalDevice, requested: u32) -> SampleCount {
+    let properties = physical_device.properties();
+    let supported =
+        properties.framebuffer_color_sample_counts & properties.framebuffer_depth_sample_counts;
+
+    [
+        (8, SampleCounts::SAMPLE_8, SampleCount::Sample8),
+        (4, SampleCounts::SAMPLE_4, SampleCount::Sample4),
+        (2, SampleCounts::SAMPLE_2, SampleCount::Sample2),
+    ]
+    .into_iter()
+    .find(|&(count, flag, _)| count <= requested && supported.contains(flag))
+    .map(|(_, _, sample_count)| sample_count)
+    .unwrap_or(SampleCount::Sample1)
+}
+
+/// Creates the shared multisampled color target the forward pass renders
+/// into before resolving into the swapchain image; resized/recreated
+/// alongside the swapchain. Only used when `sample_count != Sample1`.
+fn create_msaa_color_target(
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    color_format: Format,
+    extent: PhysicalSize<u32>,
+    sample_count: SampleCount,
+) -> anyhow::Result<Arc<ImageView>> {
+    let color_image = Image::new(
+        memory_allocator,
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: color_format,
+            extent: [extent.width, extent.height, 1],
+            samples: sample_count,
+            usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )?;
+
+    Ok(ImageView::new_default(color_image)?)
+}
+
+/// Creates the depth target every framebuffer attaches (at `sample_count`,
+/// matching the color attachment(s) it's paired with so the render pass's
+/// attachments agree on sample count), resized/recreated alongside the
+/// swapchain.
+fn create_depth_target(
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    extent: PhysicalSize<u32>,
+    sample_count: SampleCount,
+) -> anyhow::Result<Arc<ImageView>> {
+    let depth_image = Image::new(
+        memory_allocator,
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: Format::D32_SFLOAT,
+            extent: [extent.width, extent.height, 1],
+            samples: sample_count,
+            usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )?;
+
+    Ok(ImageView::new_default(depth_image)?)
+}
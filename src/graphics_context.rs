@@ -1,25 +1,114 @@
 use std::sync::Arc;
 
-use anyhow::bail;
+use anyhow::{anyhow, bail};
 
 use wgpu::{
-    Adapter, Backends, CommandBuffer, CompositeAlphaMode, Device, DeviceDescriptor, DownlevelCapabilities, DownlevelFlags, Extent3d, Features, Gles3MinorVersion, Instance, InstanceDescriptor, InstanceFlags, Limits, PresentMode, Queue, ShaderModel, Surface, SurfaceConfiguration, SurfaceTexture, Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView, TextureViewDescriptor
+    Adapter, Backends, BufferDescriptor, BufferUsages, CommandBuffer, CommandEncoderDescriptor, CompositeAlphaMode, Device, DeviceDescriptor, DownlevelCapabilities, DownlevelFlags, Extent3d, Features, Gles3MinorVersion, ImageCopyBuffer, ImageDataLayout, Instance, InstanceDescriptor, InstanceFlags, Limits, MapMode, Maintain, PowerPreference, PresentMode, Queue, RequestAdapterOptions, ShaderModel, Surface, SurfaceConfiguration, SurfaceTexture, Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureFormatFeatureFlags, TextureUsages, TextureView, TextureViewDescriptor, COPY_BYTES_PER_ROW_ALIGNMENT
 };
 use winit::{dpi::PhysicalSize, window::Window};
 
+use crate::ray_pipeline;
+
+/// An owned offscreen color target of arbitrary size/format, usable for
+/// headless rendering, screenshots, and render-to-texture post-processing.
+pub struct TextureRenderTarget {
+    pub texture: Texture,
+    pub view: TextureView,
+    pub format: TextureFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Where a frame is rendered: the presented swapchain, or an owned texture
+/// read back into CPU memory instead of displayed.
+pub enum RenderTarget {
+    Swapchain,
+    Texture(TextureRenderTarget),
+}
+
+/// Caller-supplied knobs for adapter selection, kept separate from
+/// `GraphicsContext::new`'s other parameters since more of these (backend
+/// filtering, present mode overrides) are likely to show up over time.
+#[derive(Debug, Clone, Copy)]
+pub struct GraphicsContextConfig {
+    pub power_preference: PowerPreference,
+    /// The requested MSAA sample count (1, 2, 4, or 8). `new` clamps this down
+    /// to the highest count the adapter actually supports for the chosen
+    /// surface format; the effective count ends up on `GraphicsContext::sample_count`.
+    pub msaa_samples: u32,
+}
+
+impl Default for GraphicsContextConfig {
+    fn default() -> Self {
+        Self {
+            power_preference: PowerPreference::HighPerformance,
+            msaa_samples: 4,
+        }
+    }
+}
+
+/// A wgpu device/surface/adapter bundle. Constructed by `WgpuRenderer` when
+/// `Engine` is started with `--backend wgpu`; the vulkano `GraphicsSubsystem`
+/// and this context are never both alive for the same window; that would
+/// mean two graphics APIs contending for one swapchain.
 pub struct GraphicsContext<'a> {
     _instance: Instance,
     surface: Surface<'a>,
     _adapter: Adapter,
     pub surface_config: SurfaceConfiguration,
+    pub surface_format: TextureFormat,
     pub device: Device,
-    queue: Queue,
+    pub queue: Queue,
+    /// The effective MSAA sample count, clamped to what the adapter supports.
+    /// Pipelines rendering into this context's targets must set their
+    /// `MultisampleState::count` to match.
+    pub sample_count: u32,
+    /// Whether `device` was created with `ray_pipeline::REQUIRED_FEATURES`
+    /// enabled. `Engine` falls back to the raster renderer when this is false.
+    pub ray_tracing_supported: bool,
+    msaa_texture: Option<Texture>,
+    msaa_view: Option<TextureView>,
     depth_texture: Texture,
     pub depth_texture_view: TextureView,
 }
 
+/// The view to render into for a frame, and where it resolves to when
+/// multisampled.
+pub struct Frame {
+    pub surface_texture: SurfaceTexture,
+    /// The color attachment to render into: the MSAA target when
+    /// `sample_count > 1`, otherwise the swapchain view directly.
+    pub view: TextureView,
+    /// The swapchain view `view` resolves into. `None` when `view` already
+    /// is the swapchain view (`sample_count == 1`).
+    pub resolve_target: Option<TextureView>,
+}
+
+/// Picks the highest sample count in `1, 2, 4, 8` that is both `<= requested`
+/// and supported by `adapter` for `format`.
+fn highest_supported_sample_count(adapter: &Adapter, format: TextureFormat, requested: u32) -> u32 {
+    let flags = adapter.get_texture_format_features(format).flags;
+
+    let supported = |count: u32| match count {
+        1 => true,
+        2 => flags.contains(TextureFormatFeatureFlags::MULTISAMPLE_X2),
+        4 => flags.contains(TextureFormatFeatureFlags::MULTISAMPLE_X4),
+        8 => flags.contains(TextureFormatFeatureFlags::MULTISAMPLE_X8),
+        _ => false,
+    };
+
+    [8, 4, 2, 1]
+        .into_iter()
+        .find(|&count| count <= requested.max(1) && supported(count))
+        .unwrap_or(1)
+}
+
 impl<'a> GraphicsContext<'a> {
-    pub fn new(window: Arc<Window>, physical_size: PhysicalSize<u32>) -> anyhow::Result<Self> {
+    pub fn new(
+        window: Arc<Window>,
+        physical_size: PhysicalSize<u32>,
+        config: GraphicsContextConfig,
+    ) -> anyhow::Result<Self> {
         let dx12_shader_compiler = wgpu::util::dx12_shader_compiler_from_env().unwrap_or_default();
 
         let instance_flags = InstanceFlags::from_build_config();
@@ -33,24 +122,40 @@ impl<'a> GraphicsContext<'a> {
 
         let surface = instance.create_surface(window)?;
 
-        let adapter = match futures::executor::block_on(
-            wgpu::util::initialize_adapter_from_env_or_default(&instance, Some(&surface)),
-        ) {
+        let adapter = match futures::executor::block_on(instance.request_adapter(
+            &RequestAdapterOptions {
+                power_preference: config.power_preference,
+                compatible_surface: Some(&surface),
+                force_fallback_adapter: false,
+            },
+        )) {
             Some(adapter) => adapter,
             None => bail!("Unable to find a suitable display adapter"),
         };
 
+        let adapter_info = adapter.get_info();
+        tracing::info!(
+            name = %adapter_info.name,
+            backend = ?adapter_info.backend,
+            "selected graphics adapter"
+        );
+
         let capabilities = surface.get_capabilities(&adapter);
-        let surface_format = match capabilities
-            .formats
-            .contains(&TextureFormat::Bgra8UnormSrgb)
-        {
-            true => TextureFormat::Bgra8UnormSrgb,
-            false => bail!("The adapter has no supported surface formats"),
-        };
+        // Prefer an sRGB BGRA/RGBA surface so the rest of the pipeline can
+        // assume sRGB output; fall back through any other sRGB format and
+        // finally whatever the adapter offers, since GLES/mobile backends
+        // often don't expose BGRA at all.
+        let surface_format = [TextureFormat::Bgra8UnormSrgb, TextureFormat::Rgba8UnormSrgb]
+            .into_iter()
+            .find(|format| capabilities.formats.contains(format))
+            .or_else(|| capabilities.formats.iter().copied().find(|format| format.is_srgb()))
+            .or_else(|| capabilities.formats.first().copied())
+            .ok_or_else(|| anyhow!("The adapter has no supported surface formats"))?;
 
         let surface_config = SurfaceConfiguration {
-            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_DST,
+            // `COPY_SRC` so the presented swapchain image can be read back for
+            // screenshots (see `WgpuRenderer::request_screenshot`).
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_DST | TextureUsages::COPY_SRC,
             format: surface_format,
             width: physical_size.width,
             height: physical_size.height,
@@ -60,7 +165,7 @@ impl<'a> GraphicsContext<'a> {
             desired_maximum_frame_latency: 5,
         };
 
-        let required_features = Features::PUSH_CONSTANTS
+        let mut required_features = Features::PUSH_CONSTANTS
             | Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
             | Features::CLEAR_TEXTURE
             | Features::CONSERVATIVE_RASTERIZATION;
@@ -71,6 +176,14 @@ impl<'a> GraphicsContext<'a> {
             bail!("The adapter doesn't contain the required features");
         }
 
+        // Ray tracing is optional: only request it (and only report it supported)
+        // when the adapter actually has it, so adapters without it still get a
+        // device for the raster path instead of failing `request_device` outright.
+        let ray_tracing_supported = adapter_features.contains(ray_pipeline::REQUIRED_FEATURES);
+        if ray_tracing_supported {
+            required_features |= ray_pipeline::REQUIRED_FEATURES;
+        }
+
         let required_downlevel_capabilities = DownlevelCapabilities {
             flags: DownlevelFlags::empty(),
             shader_model: ShaderModel::Sm5,
@@ -107,15 +220,24 @@ impl<'a> GraphicsContext<'a> {
 
         surface.configure(&device, &surface_config);
 
-        let (depth_texture, depth_texture_view) = create_depth_texture(&device, physical_size);
+        let sample_count = highest_supported_sample_count(&adapter, surface_format, config.msaa_samples);
+
+        let (depth_texture, depth_texture_view) = create_depth_texture(&device, physical_size, sample_count);
+        let (msaa_texture, msaa_view) = create_msaa_texture(&device, surface_format, physical_size, sample_count)
+            .map_or((None, None), |(texture, view)| (Some(texture), Some(view)));
 
         Ok(Self {
             _instance: instance,
             surface,
             _adapter: adapter,
             surface_config,
+            surface_format,
             device,
             queue,
+            sample_count,
+            ray_tracing_supported,
+            msaa_texture,
+            msaa_view,
             depth_texture: depth_texture,
             depth_texture_view,
         })
@@ -127,7 +249,15 @@ impl<'a> GraphicsContext<'a> {
 
         if self.surface_config.width * self.surface_config.height != 0 {
             self.surface.configure(&self.device, &self.surface_config);
-            (self.depth_texture, self.depth_texture_view) = create_depth_texture(&self.device, physical_size);
+            (self.depth_texture, self.depth_texture_view) =
+                create_depth_texture(&self.device, physical_size, self.sample_count);
+            (self.msaa_texture, self.msaa_view) = create_msaa_texture(
+                &self.device,
+                self.surface_format,
+                physical_size,
+                self.sample_count,
+            )
+            .map_or((None, None), |(texture, view)| (Some(texture), Some(view)));
         }
     }
 
@@ -138,12 +268,12 @@ impl<'a> GraphicsContext<'a> {
     /// If the surface is lost, it will be recreated.
     ///
     /// If the surface is lost and recreation fails, this function will panic.
-    pub fn get_frame(&self) -> Option<(SurfaceTexture, TextureView)> {
+    pub fn get_frame(&self) -> Option<Frame> {
         if self.surface_config.width * self.surface_config.height == 0 {
             return None;
         }
 
-        let frame = match self.surface.get_current_texture() {
+        let surface_texture = match self.surface.get_current_texture() {
             Ok(frame) => frame,
             Err(_) => {
                 self.surface.configure(&self.device, &self.surface_config);
@@ -153,24 +283,170 @@ impl<'a> GraphicsContext<'a> {
             }
         };
 
-        let frame_view = frame.texture.create_view(&TextureViewDescriptor::default());
+        let surface_view = surface_texture
+            .texture
+            .create_view(&TextureViewDescriptor::default());
+
+        let (view, resolve_target) = match &self.msaa_view {
+            Some(msaa_view) => (msaa_view.clone(), Some(surface_view)),
+            None => (surface_view, None),
+        };
 
-        Some((frame, frame_view))
+        Some(Frame {
+            surface_texture,
+            view,
+            resolve_target,
+        })
     }
 
     pub fn submit<I: IntoIterator<Item = CommandBuffer>>(&self, command_buffers: I) {
         self.queue.submit(command_buffers);
     }
+
+    /// Creates an owned color target of `width`x`height` with `COPY_SRC`
+    /// usage so it can later be read back via `read_texture_target`.
+    pub fn create_texture_target(&self, width: u32, height: u32, format: TextureFormat) -> RenderTarget {
+        let texture = self.device.create_texture(&TextureDescriptor {
+            label: Some("render_target"),
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[format],
+        });
+
+        let view = texture.create_view(&TextureViewDescriptor::default());
+
+        RenderTarget::Texture(TextureRenderTarget {
+            texture,
+            view,
+            format,
+            width,
+            height,
+        })
+    }
+
+    /// Copies `target`'s texture into a mapped staging buffer and returns its
+    /// pixels with row padding stripped. `target`'s format must have a known
+    /// block copy size (i.e. not a compressed or multi-planar format).
+    pub fn read_texture_target(&self, target: &TextureRenderTarget) -> anyhow::Result<Vec<u8>> {
+        self.read_texture(&target.texture, target.format, target.width, target.height)
+    }
+
+    /// Copies `width`x`height` of `texture` (which must have `COPY_SRC` usage
+    /// and `format` as its native format) into a mapped staging buffer and
+    /// returns its pixels with row padding stripped. `format` must have a
+    /// known block copy size (i.e. not a compressed or multi-planar format).
+    ///
+    /// Used both by `read_texture_target` and directly by `WgpuRenderer` to
+    /// read back the presented swapchain texture for screenshots, which has
+    /// no owned `TextureRenderTarget` of its own.
+    pub fn read_texture(&self, texture: &Texture, format: TextureFormat, width: u32, height: u32) -> anyhow::Result<Vec<u8>> {
+        let bytes_per_pixel = format
+            .block_copy_size(None)
+            .ok_or_else(|| anyhow!("{:?} has no fixed block copy size to read back", format))?;
+
+        // The staging buffer's row stride must be a multiple of
+        // `COPY_BYTES_PER_ROW_ALIGNMENT`, which the texture's actual width
+        // rarely satisfies on its own.
+        let unpadded_bytes_per_row = width * bytes_per_pixel;
+        let padded_bytes_per_row = unpadded_bytes_per_row.next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let staging_buffer = self.device.create_buffer(&BufferDescriptor {
+            label: Some("texture_readback"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor { label: Some("texture_readback") });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            ImageCopyBuffer {
+                buffer: &staging_buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit([encoder.finish()]);
+
+        let slice = staging_buffer.slice(..);
+        let (tx, rx) = futures::channel::oneshot::channel();
+        slice.map_async(MapMode::Read, move |result| {
+            let _ = tx.send(result);
+        });
+        self.device.poll(Maintain::Wait);
+        futures::executor::block_on(rx)??;
+
+        let padded = slice.get_mapped_range();
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        for row in padded.chunks(padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+        drop(padded);
+        staging_buffer.unmap();
+
+        Ok(pixels)
+    }
 }
 
-fn create_depth_texture(device: &Device, physical_size: PhysicalSize<u32>) -> (Texture, TextureView) {
+fn create_depth_texture(device: &Device, physical_size: PhysicalSize<u32>, sample_count: u32) -> (Texture, TextureView) {
     let texture = device.create_texture(&TextureDescriptor { label: Some("depth"), size: Extent3d {
         width: physical_size.width,
         height: physical_size.height,
         depth_or_array_layers: 1,
-    }, mip_level_count: 1, sample_count: 1, dimension: TextureDimension::D2, format: TextureFormat::Depth32Float, usage: TextureUsages::RENDER_ATTACHMENT, view_formats: &[TextureFormat::Depth32Float]});
+    }, mip_level_count: 1, sample_count, dimension: TextureDimension::D2, format: TextureFormat::Depth32Float, usage: TextureUsages::RENDER_ATTACHMENT, view_formats: &[TextureFormat::Depth32Float]});
 
     let view = texture.create_view(&TextureViewDescriptor::default());
 
     (texture, view)
 }
+
+/// Creates the multisampled color target that gets resolved into the
+/// swapchain image each frame, or `None` when `sample_count` is 1 and frames
+/// render directly into the swapchain.
+fn create_msaa_texture(
+    device: &Device,
+    format: TextureFormat,
+    physical_size: PhysicalSize<u32>,
+    sample_count: u32,
+) -> Option<(Texture, TextureView)> {
+    if sample_count == 1 {
+        return None;
+    }
+
+    let texture = device.create_texture(&TextureDescriptor {
+        label: Some("msaa_color"),
+        size: Extent3d {
+            width: physical_size.width,
+            height: physical_size.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: TextureDimension::D2,
+        format,
+        usage: TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[format],
+    });
+
+    let view = texture.create_view(&TextureViewDescriptor::default());
+
+    Some((texture, view))
+}
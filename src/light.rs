@@ -0,0 +1,102 @@
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3};
+
+/// Selects how the shadow map is sampled when shading a fragment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShadowFilter {
+    /// A single hardware-accelerated 2x2 comparison sample.
+    Hardware2x2,
+    /// An NxN grid of comparison samples offset by one texel, averaged. An
+    /// even `taps` samples the next smaller odd grid (e.g. 4 gives 3x3).
+    Pcf { taps: u32 },
+    /// Percentage-closer soft shadows: a blocker search estimates the
+    /// penumbra width, then PCF samples over a kernel scaled to match it.
+    Pcss {
+        /// Size of the blocker-search region, in shadow-map texels.
+        search_radius: f32,
+        /// World-space size of the light, used to scale the penumbra estimate.
+        light_size: f32,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ShadowSettings {
+    pub filter: ShadowFilter,
+    /// Depth bias applied in light space to avoid shadow acne / peter-panning.
+    pub depth_bias: f32,
+    pub map_size: u32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            filter: ShadowFilter::Pcf { taps: 3 },
+            depth_bias: 0.0025,
+            map_size: 2048,
+        }
+    }
+}
+
+/// A single directional light (e.g. the sun) with an associated shadow map.
+#[derive(Debug, Clone, Copy)]
+pub struct Light {
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub shadow: ShadowSettings,
+}
+
+impl Light {
+    /// Computes the view-projection matrix used both to render the shadow
+    /// map and to transform fragments into light space in the main pass.
+    ///
+    /// `center` and `radius` should bound the scene (or the portion of it
+    /// this light needs to cover) so the orthographic frustum is as tight
+    /// as possible.
+    pub fn view_projection(&self, center: Vec3, radius: f32) -> Mat4 {
+        let direction = self.direction.normalize();
+        let eye = center - direction * radius;
+        let up = if direction.abs().dot(Vec3::Y) > 0.999 {
+            Vec3::Z
+        } else {
+            Vec3::Y
+        };
+
+        let view = Mat4::look_at_rh(eye, center, up);
+        let projection = Mat4::orthographic_rh(-radius, radius, -radius, radius, 0.0, radius * 2.0);
+
+        projection * view
+    }
+
+    /// Builds the GPU-side shadow sampling parameters for `assets/mesh.wgsl`.
+    pub fn shadow_params(&self) -> ShadowParamsUniform {
+        let (search_radius, light_size, pcf_radius, filter_mode) = match self.shadow.filter {
+            ShadowFilter::Hardware2x2 => (0.0, 0.0, 0, 0),
+            // An NxN tap grid is centered on the sample, i.e. a radius of `(N - 1) / 2` texels.
+            ShadowFilter::Pcf { taps } => (0.0, 0.0, taps.saturating_sub(1) / 2, 1),
+            ShadowFilter::Pcss {
+                search_radius,
+                light_size,
+            } => (search_radius, light_size, 0, 2),
+        };
+
+        ShadowParamsUniform {
+            light_size,
+            search_radius,
+            texel_size: 1.0 / self.shadow.map_size as f32,
+            pcf_radius,
+            filter_mode,
+        }
+    }
+}
+
+/// Mirrors `ShadowParams` in `assets/include/shadow_sampling.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, Pod, Zeroable)]
+pub struct ShadowParamsUniform {
+    pub light_size: f32,
+    pub search_radius: f32,
+    pub texel_size: f32,
+    /// Half-width, in shadow-map texels, of the `ShadowFilter::Pcf` tap grid.
+    pub pcf_radius: u32,
+    pub filter_mode: u32,
+}
@@ -2,11 +2,25 @@
 
 mod engine;
 mod asset;
+mod camera;
+mod camera_controller;
+mod compute_pipeline;
+mod graphics;
 mod graphics_context;
+mod light;
+mod material;
+mod mesh;
+mod mesh_pipeline;
+mod ray_pipeline;
+mod render_graph;
+mod shader_preprocessor;
+mod shadow_pipeline;
 mod state;
+mod uniform_storage;
+mod wgpu_renderer;
 
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
@@ -17,10 +31,72 @@ struct Cli {
 
 #[derive(Clone, Debug, Subcommand)]
 enum Command {
-    Launch,
+    Launch {
+        /// Which render path to use. Defaults to `raster`, or to `ray-traced`'s raster
+        /// fallback when the adapter doesn't support the required ray tracing features.
+        #[arg(long, value_enum, default_value_t = RendererArg::Raster)]
+        renderer: RendererArg,
+        /// Which camera controller to use: an arcball-style orbit camera, or a
+        /// WASD/mouse-look fly camera.
+        #[arg(long, value_enum, default_value_t = CameraArg::Orbit)]
+        camera: CameraArg,
+        /// Which graphics backend to render through. Defaults to the
+        /// battle-tested vulkano backend; `wgpu` is where ray tracing,
+        /// compute-driven particles, and the render graph actually run.
+        #[arg(long, value_enum, default_value_t = BackendArg::Vulkano)]
+        backend: BackendArg,
+    },
     Update,
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum RendererArg {
+    #[default]
+    Raster,
+    RayTraced,
+}
+
+impl From<RendererArg> for ray_pipeline::RendererKind {
+    fn from(arg: RendererArg) -> Self {
+        match arg {
+            RendererArg::Raster => ray_pipeline::RendererKind::Raster,
+            RendererArg::RayTraced => ray_pipeline::RendererKind::RayTraced,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum CameraArg {
+    #[default]
+    Orbit,
+    Fly,
+}
+
+impl From<CameraArg> for camera_controller::CameraKind {
+    fn from(arg: CameraArg) -> Self {
+        match arg {
+            CameraArg::Orbit => camera_controller::CameraKind::Orbit,
+            CameraArg::Fly => camera_controller::CameraKind::Fly,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum BackendArg {
+    #[default]
+    Vulkano,
+    Wgpu,
+}
+
+impl From<BackendArg> for engine::Backend {
+    fn from(arg: BackendArg) -> Self {
+        match arg {
+            BackendArg::Vulkano => engine::Backend::Vulkano,
+            BackendArg::Wgpu => engine::Backend::Wgpu,
+        }
+    }
+}
+
 fn main() -> Result<()> {
     #[cfg(windows)]
     unsafe {
@@ -34,8 +110,14 @@ fn main() -> Result<()> {
 
     tracing_subscriber::fmt::init();
 
-    match Cli::parse().command.unwrap_or(Command::Launch) {
-        Command::Launch => engine::Engine::new()?.run()?,
+    match Cli::parse().command.unwrap_or(Command::Launch {
+        renderer: RendererArg::default(),
+        camera: CameraArg::default(),
+        backend: BackendArg::default(),
+    }) {
+        Command::Launch { renderer, camera, backend } => {
+            engine::Engine::new(None, renderer.into(), camera.into(), backend.into())?.run()?
+        }
         Command::Update => {
             let status = self_update::backends::github::Update::configure()
                 .repo_owner("chaynabors")
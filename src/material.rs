@@ -0,0 +1,139 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferUsages,
+    Extent3d, FilterMode, SamplerBindingType, SamplerDescriptor, ShaderStages, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureViewDescriptor,
+    TextureViewDimension,
+};
+
+use crate::graphics_context::GraphicsContext;
+
+/// Mirrors the per-material uniform read by `assets/mesh.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, Pod, Zeroable)]
+pub struct MaterialUniform {
+    pub base_color_factor: [f32; 4],
+}
+
+pub struct Material {
+    pub base_color_factor: [f32; 4],
+    pub bind_group: BindGroup,
+}
+
+/// Layout of the per-material bind group: a base-color texture + sampler and the material factors.
+pub fn material_bind_group_layout(ctx: &GraphicsContext) -> BindGroupLayout {
+    ctx.device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("material_bind_group_layout"),
+        entries: &[
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 2,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+/// Uploads a decoded base-color image (or a single opaque white texel when a
+/// primitive has none) and builds its material bind group.
+pub fn load_material(
+    ctx: &GraphicsContext,
+    layout: &BindGroupLayout,
+    base_color_factor: [f32; 4],
+    base_color_image: Option<(&[u8], u32, u32)>,
+) -> Material {
+    let (rgba, width, height) = match base_color_image {
+        Some((rgba, width, height)) => (rgba.to_vec(), width, height),
+        None => (vec![255, 255, 255, 255], 1, 1),
+    };
+
+    let texture = ctx.device.create_texture(&TextureDescriptor {
+        label: Some("material_base_color"),
+        size: Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::Rgba8UnormSrgb,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    ctx.queue.write_texture(
+        texture.as_image_copy(),
+        &rgba,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * width),
+            rows_per_image: Some(height),
+        },
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+
+    let view = texture.create_view(&TextureViewDescriptor::default());
+    let sampler = ctx.device.create_sampler(&SamplerDescriptor {
+        mag_filter: FilterMode::Linear,
+        min_filter: FilterMode::Linear,
+        ..Default::default()
+    });
+
+    let uniform = ctx.device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("material_uniform"),
+        contents: bytemuck::bytes_of(&MaterialUniform { base_color_factor }),
+        usage: BufferUsages::UNIFORM,
+    });
+
+    let bind_group = ctx.device.create_bind_group(&BindGroupDescriptor {
+        label: Some("material_bind_group"),
+        layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&view),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Sampler(&sampler),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: uniform.as_entire_binding(),
+            },
+        ],
+    });
+
+    Material {
+        base_color_factor,
+        bind_group,
+    }
+}
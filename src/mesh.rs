@@ -1,37 +1,63 @@
+use std::ops::Range;
+
 use anyhow::Result;
 use gltf::mesh::{util::ReadIndices, Mode};
 use tracing::warn;
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
-    Buffer, BufferUsages,
+    BindGroupLayout, Buffer, BufferUsages, IndexFormat,
+};
+
+use crate::{
+    graphics_context::GraphicsContext,
+    material::{self, Material},
 };
 
-use crate::graphics_context::GraphicsContext;
+/// A contiguous run of indices drawn with a single material.
+pub struct Primitive {
+    pub index_range: Range<u32>,
+    pub material: usize,
+}
 
 pub struct Mesh {
     pub positions: Buffer,
+    pub normals: Buffer,
+    pub uvs: Buffer,
     pub colors: Buffer,
     pub indices: Buffer,
-    pub index_count: u32,
+    pub index_format: IndexFormat,
+    pub primitives: Vec<Primitive>,
+    pub materials: Vec<Material>,
 }
 
 impl Mesh {
-    pub fn load(asset: &[u8], gfx: &mut GraphicsContext) -> Result<Self> {
-        let (document, buffers, _images) = gltf::import_slice(asset)?;
+    pub fn load(
+        asset: &[u8],
+        gfx: &mut GraphicsContext,
+        material_bind_group_layout: &BindGroupLayout,
+    ) -> Result<Self> {
+        let (document, buffers, images) = gltf::import_slice(asset)?;
 
         let mut positions = vec![];
-        let mut indices = vec![];
+        let mut normals = vec![];
+        let mut uvs = vec![];
         let mut colors = vec![];
-        let mut position_offset ;
+        let mut indices = vec![];
+        let mut gltf_primitives = vec![];
+
         for scene in document.scenes() {
             for node in scene.nodes() {
-                position_offset = positions.len();
-
                 let Some(mesh) = node.mesh() else {
                     continue;
                 };
 
                 for primitive in mesh.primitives() {
+                    // Recomputed per primitive, not per node: `positions` (and thus the
+                    // global index base every primitive's indices are offset by) grows
+                    // with every primitive pushed below, so a node's second and later
+                    // primitives would otherwise index into the previous primitive's vertices.
+                    let position_offset = positions.len();
+
                     if primitive.mode() != Mode::Triangles {
                         warn!("encountered non-triangle geometry during geometry import");
                         continue;
@@ -42,34 +68,104 @@ impl Mesh {
                         warn!("encountered geometry with no position attribute during geometry import");
                         continue;
                     };
+                    let vertex_count = pos_iter.len();
+                    positions.extend(pos_iter);
 
-                    let Some(col_iter) = reader.read_colors(0) else {
-                        warn!(
-                            "encountered geometry with no color attribute 0 during geometry import"
-                        );
+                    match reader.read_normals() {
+                        Some(normal_iter) => normals.extend(normal_iter),
+                        // Generate a flat normal per vertex from the primitive's triangles below.
+                        None => normals.extend(std::iter::repeat([0.0, 0.0, 0.0]).take(vertex_count)),
+                    }
+
+                    match reader.read_tex_coords(0) {
+                        Some(uv_iter) => uvs.extend(uv_iter.into_f32()),
+                        None => uvs.extend(std::iter::repeat([0.0, 0.0]).take(vertex_count)),
+                    }
+
+                    match reader.read_colors(0) {
+                        Some(col_iter) => colors.extend(col_iter.into_rgb_f32()),
+                        None => colors.extend(std::iter::repeat([1.0, 1.0, 1.0]).take(vertex_count)),
+                    }
+
+                    let Some(read_indices) = reader.read_indices() else {
+                        warn!("encountered geometry with no indices during geometry import");
                         continue;
                     };
 
-                    let Some(ReadIndices::U16(indices_iter)) = reader.read_indices() else {
-                        warn!(
-                            "encountered geometry with an unsupported index type during geometry import"
+                    let index_start = indices.len() as u32;
+                    match read_indices {
+                        ReadIndices::U8(iter) => {
+                            indices.extend(iter.map(|i| position_offset as u32 + i as u32))
+                        }
+                        ReadIndices::U16(iter) => {
+                            indices.extend(iter.map(|i| position_offset as u32 + i as u32))
+                        }
+                        ReadIndices::U32(iter) => {
+                            indices.extend(iter.map(|i| position_offset as u32 + i))
+                        }
+                    }
+                    let index_end = indices.len() as u32;
+
+                    if normals[normals.len() - vertex_count..]
+                        .iter()
+                        .all(|n: &[f32; 3]| *n == [0.0, 0.0, 0.0])
+                    {
+                        compute_flat_normals(
+                            &mut normals,
+                            &positions,
+                            &indices[index_start as usize..index_end as usize],
                         );
-                        continue;
-                    };
+                    }
 
-                    positions.extend(pos_iter);
-                    colors.extend(col_iter.into_rgb_f32());
-                    indices.extend(indices_iter.map(|i| i + position_offset as u16));
+                    let material = primitive.material();
+                    let pbr = material.pbr_metallic_roughness();
+                    let base_color_image = pbr.base_color_texture().and_then(|info| {
+                        let image = &images[info.texture().source().index()];
+                        rgba_from_image(image)
+                    });
+
+                    gltf_primitives.push((
+                        index_start..index_end,
+                        pbr.base_color_factor(),
+                        base_color_image,
+                    ));
                 }
             }
         }
 
+        let mut materials = vec![];
+        let mut primitives = vec![];
+        for (index_range, base_color_factor, base_color_image) in gltf_primitives {
+            materials.push(material::load_material(
+                gfx,
+                material_bind_group_layout,
+                base_color_factor,
+                base_color_image
+                    .as_ref()
+                    .map(|(rgba, width, height)| (rgba.as_slice(), *width, *height)),
+            ));
+            primitives.push(Primitive {
+                index_range,
+                material: materials.len() - 1,
+            });
+        }
+
         Ok(Self {
             positions: gfx.device.create_buffer_init(&BufferInitDescriptor {
                 label: None,
                 contents: bytemuck::cast_slice(&positions),
                 usage: BufferUsages::VERTEX,
             }),
+            normals: gfx.device.create_buffer_init(&BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(&normals),
+                usage: BufferUsages::VERTEX,
+            }),
+            uvs: gfx.device.create_buffer_init(&BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(&uvs),
+                usage: BufferUsages::VERTEX,
+            }),
             colors: gfx.device.create_buffer_init(&BufferInitDescriptor {
                 label: None,
                 contents: bytemuck::cast_slice(&colors),
@@ -80,7 +176,46 @@ impl Mesh {
                 contents: bytemuck::cast_slice(&indices),
                 usage: BufferUsages::INDEX,
             }),
-            index_count: indices.len().try_into().unwrap(),
+            index_format: IndexFormat::Uint32,
+            primitives,
+            materials,
         })
     }
 }
+
+/// Decodes a glTF image into tightly-packed RGBA8, widening RGB8 as needed.
+/// Any other format is skipped with a warning and yields `None`.
+fn rgba_from_image(image: &gltf::image::Data) -> Option<(Vec<u8>, u32, u32)> {
+    use gltf::image::Format;
+
+    let rgba = match image.format {
+        Format::R8G8B8A8 => image.pixels.clone(),
+        Format::R8G8B8 => image
+            .pixels
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+            .collect(),
+        other => {
+            warn!(?other, "unsupported glTF base color image format, skipping texture");
+            return None;
+        }
+    };
+
+    Some((rgba, image.width, image.height))
+}
+
+/// Fills in the flat (face) normal for each vertex touched by `indices`,
+/// used when a primitive doesn't supply its own normals.
+fn compute_flat_normals(normals: &mut [[f32; 3]], positions: &[[f32; 3]], indices: &[u32]) {
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| {
+            glam::Vec3::from(positions[i as usize])
+        });
+
+        let normal = (b - a).cross(c - a).normalize_or_zero();
+
+        for &i in triangle {
+            normals[i as usize] = normal.into();
+        }
+    }
+}
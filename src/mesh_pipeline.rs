@@ -1,34 +1,101 @@
+use std::collections::HashSet;
+
 use bytemuck::{Pod, Zeroable};
 use glam::Mat4;
 use wgpu::{
-    vertex_attr_array, BlendState, BufferAddress, ColorTargetState, ColorWrites, CompareFunction, DepthBiasState, DepthStencilState, FragmentState, FrontFace, MultisampleState, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, PushConstantRange, RenderPipeline, RenderPipelineDescriptor, ShaderStages, StencilState, TextureFormat, VertexBufferLayout, VertexState, VertexStepMode
+    vertex_attr_array, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
+    BindingType, BlendState, BufferAddress, BufferBindingType, ColorTargetState, ColorWrites,
+    CompareFunction, DepthBiasState, DepthStencilState, FragmentState, FrontFace,
+    MultisampleState, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology,
+    PushConstantRange, RenderPipeline, RenderPipelineDescriptor, SamplerBindingType,
+    ShaderSource, ShaderStages, StencilState, TextureFormat, TextureSampleType,
+    TextureViewDimension, VertexBufferLayout, VertexState, VertexStepMode,
 };
 
 use super::graphics_context::GraphicsContext;
+use crate::shader_preprocessor;
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
 pub struct PushConstants {
     pub mvp: Mat4,
+    /// Transforms a mesh-space position into the light's clip space, for shadow sampling.
+    pub light_mvp: Mat4,
 }
 
-pub fn model_pipeline(ctx: &GraphicsContext) -> RenderPipeline {
-    let shader_module = ctx
-        .device
-        .create_shader_module(wgpu::include_wgsl!("assets/mesh.wgsl"));
+/// Layout of the bind group carrying the directional light's shadow map,
+/// samplers, and filtering parameters (see `assets/mesh.wgsl`).
+pub fn shadow_bind_group_layout(ctx: &GraphicsContext) -> BindGroupLayout {
+    ctx.device
+        .create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("shadow_bind_group_layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Depth,
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Comparison),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::NonFiltering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        // Backed by `UniformStorage`'s per-frame ring buffer: the bind
+                        // group is built once against it, and each draw supplies this
+                        // frame's allocation as a dynamic offset.
+                        has_dynamic_offset: true,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        })
+}
+
+pub fn model_pipeline(
+    ctx: &GraphicsContext,
+    shadow_bind_group_layout: &BindGroupLayout,
+    material_bind_group_layout: &BindGroupLayout,
+    flags: &HashSet<&str>,
+) -> anyhow::Result<RenderPipeline> {
+    let source = shader_preprocessor::preprocess(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/src/assets/mesh.wgsl"),
+        flags,
+    )?;
+    let shader_module = ctx.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("model_renderer_shader_module"),
+        source: ShaderSource::Wgsl(source.into()),
+    });
 
     let pipeline_layout = ctx
         .device
         .create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("model_renderer_pipeline_layout_descriptor"),
-            bind_group_layouts: &[],
+            bind_group_layouts: &[shadow_bind_group_layout, material_bind_group_layout],
             push_constant_ranges: &[PushConstantRange {
                 stages: ShaderStages::VERTEX,
                 range: 0..u32::try_from(std::mem::size_of::<PushConstants>()).unwrap(),
             }],
         });
 
-    ctx.device
+    Ok(ctx.device
         .create_render_pipeline(&RenderPipelineDescriptor {
             label: Some("model_renderer_pipeline"),
             layout: Some(&pipeline_layout),
@@ -46,6 +113,16 @@ pub fn model_pipeline(ctx: &GraphicsContext) -> RenderPipeline {
                         step_mode: VertexStepMode::Vertex,
                         attributes: &vertex_attr_array![1 => Float32x3],
                     },
+                    VertexBufferLayout {
+                        array_stride: std::mem::size_of::<[f32; 3]>() as BufferAddress,
+                        step_mode: VertexStepMode::Vertex,
+                        attributes: &vertex_attr_array![2 => Float32x3],
+                    },
+                    VertexBufferLayout {
+                        array_stride: std::mem::size_of::<[f32; 2]>() as BufferAddress,
+                        step_mode: VertexStepMode::Vertex,
+                        attributes: &vertex_attr_array![3 => Float32x2],
+                    },
                 ],
             },
             primitive: PrimitiveState {
@@ -64,7 +141,10 @@ pub fn model_pipeline(ctx: &GraphicsContext) -> RenderPipeline {
                 stencil: StencilState::default(),
                 bias: DepthBiasState::default(),
             }),
-            multisample: MultisampleState::default(),
+            multisample: MultisampleState {
+                count: ctx.sample_count,
+                ..Default::default()
+            },
             fragment: Some(FragmentState {
                 module: &shader_module,
                 entry_point: "fs_main",
@@ -75,5 +155,5 @@ pub fn model_pipeline(ctx: &GraphicsContext) -> RenderPipeline {
                 })],
             }),
             multiview: None,
-        })
+        }))
 }
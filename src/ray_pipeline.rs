@@ -0,0 +1,223 @@
+use bytemuck::{Pod, Zeroable};
+use glam::Mat4;
+use wgpu::{
+    Adapter, AccelerationStructureFlags, AccelerationStructureGeometryFlags,
+    AccelerationStructureUpdateMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Blas, BlasBuildEntry,
+    BlasGeometries, BlasGeometrySizeDescriptors, BlasTriangleGeometry, BlasTriangleGeometrySizeDescriptor, Buffer,
+    BufferBindingType, CommandEncoder, ComputePipeline, ComputePipelineDescriptor, CreateBlasDescriptor,
+    CreateTlasDescriptor, Features, IndexFormat, PipelineLayoutDescriptor, ShaderStages, StorageTextureAccess,
+    TextureFormat, TextureView, TextureViewDimension, TlasInstance, TlasPackage, VertexFormat,
+};
+
+use crate::graphics_context::GraphicsContext;
+
+/// Mirrors `RayParams` in `assets/raytrace.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, Pod, Zeroable)]
+pub struct RayParamsUniform {
+    pub light_direction: [f32; 3],
+    pub reflections_enabled: u32,
+}
+
+/// The set of adapter features required to run the ray-traced renderer.
+/// `GraphicsContext::new` only enables them when the adapter has them, and
+/// `WgpuRenderer::new` falls back to the raster `model_pipeline` when it doesn't.
+pub const REQUIRED_FEATURES: Features =
+    Features::EXPERIMENTAL_RAY_QUERY.union(Features::EXPERIMENTAL_RAY_TRACING_ACCELERATION_STRUCTURE);
+
+/// Which render path the engine should use, resolved once at startup from
+/// adapter support and the `--renderer` CLI flag.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RendererKind {
+    #[default]
+    Raster,
+    RayTraced,
+}
+
+pub fn adapter_supports_ray_tracing(adapter: &Adapter) -> bool {
+    adapter.features().contains(REQUIRED_FEATURES)
+}
+
+/// Returns the descriptor for an (unbuilt) bottom-level acceleration
+/// structure sized for a mesh's position/index buffers, and the geometry
+/// size descriptor that both `Device::create_blas` and `record_blas_build`
+/// need. The caller creates the BLAS itself.
+pub fn create_blas(vertex_count: u32, index_count: u32) -> (CreateBlasDescriptor<'static>, BlasTriangleGeometrySizeDescriptor) {
+    let size_desc = BlasTriangleGeometrySizeDescriptor {
+        vertex_format: VertexFormat::Float32x3,
+        vertex_count,
+        index_format: Some(IndexFormat::Uint32),
+        index_count: Some(index_count),
+        flags: AccelerationStructureGeometryFlags::OPAQUE,
+    };
+
+    let descriptor = CreateBlasDescriptor {
+        label: Some("mesh_blas"),
+        flags: AccelerationStructureFlags::PREFER_FAST_TRACE,
+        update_mode: AccelerationStructureUpdateMode::Build,
+    };
+
+    (descriptor, size_desc)
+}
+
+/// Records a BLAS build from a mesh's position/index buffers into `encoder`.
+pub fn record_blas_build(
+    encoder: &mut CommandEncoder,
+    blas: &Blas,
+    size_desc: &BlasTriangleGeometrySizeDescriptor,
+    positions: &Buffer,
+    indices: &Buffer,
+) {
+    let geometry = BlasTriangleGeometry {
+        size: size_desc,
+        vertex_buffer: positions,
+        first_vertex: 0,
+        vertex_stride: std::mem::size_of::<[f32; 3]>() as u64,
+        index_buffer: Some(indices),
+        first_index: Some(0),
+        transform_buffer: None,
+        transform_buffer_offset: None,
+    };
+
+    let entry = BlasBuildEntry {
+        blas,
+        geometry: BlasGeometries::TriangleGeometries(vec![geometry]),
+    };
+
+    encoder.build_acceleration_structures(std::iter::once(&entry), std::iter::empty());
+}
+
+/// Builds the top-level acceleration structure from the per-player
+/// transforms in `SynchronizedState`, one instance per player referencing
+/// the same mesh BLAS.
+pub fn build_tlas(ctx: &GraphicsContext, blas: &Blas, player_transforms: &[Mat4]) -> TlasPackage {
+    let tlas = ctx.device.create_tlas(&CreateTlasDescriptor {
+        label: Some("scene_tlas"),
+        max_instances: player_transforms.len() as u32,
+        flags: AccelerationStructureFlags::PREFER_FAST_TRACE,
+        update_mode: AccelerationStructureUpdateMode::Build,
+    });
+
+    let mut package = TlasPackage::new(tlas);
+    for (index, transform) in player_transforms.iter().enumerate() {
+        // `TlasInstance` takes a row-major 3x4 affine transform.
+        let row_major = transform.transpose().to_cols_array();
+        *package.get_mut_single(index).unwrap() =
+            Some(TlasInstance::new(blas, row_major[..12].try_into().unwrap(), 0, 0xff));
+    }
+
+    package
+}
+
+pub fn record_tlas_build(encoder: &mut CommandEncoder, package: &TlasPackage) {
+    encoder.build_acceleration_structures(std::iter::empty::<&BlasBuildEntry>(), std::iter::once(package));
+}
+
+/// Layout of the bind group `assets/raytrace.wgsl` reads through: the TLAS,
+/// its storage-texture output, and the `RayParams` uniform.
+pub fn ray_trace_bind_group_layout(ctx: &GraphicsContext) -> BindGroupLayout {
+    ctx.device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("ray_trace_bind_group_layout"),
+        entries: &[
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::COMPUTE,
+                ty: BindingType::AccelerationStructure { vertex_return: false },
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::COMPUTE,
+                ty: BindingType::StorageTexture {
+                    access: StorageTextureAccess::WriteOnly,
+                    format: TextureFormat::Rgba8Unorm,
+                    view_dimension: TextureViewDimension::D2,
+                },
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 2,
+                visibility: ShaderStages::COMPUTE,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+/// Builds the bind group `ray_trace_bind_group_layout` describes.
+pub fn build_ray_trace_bind_group(
+    ctx: &GraphicsContext,
+    layout: &BindGroupLayout,
+    tlas_package: &TlasPackage,
+    output_view: &TextureView,
+    params_buffer: &Buffer,
+) -> BindGroup {
+    ctx.device.create_bind_group(&BindGroupDescriptor {
+        label: Some("ray_trace_bind_group"),
+        layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                // `TlasPackage` derefs to the `Tlas` it wraps.
+                resource: BindingResource::AccelerationStructure(tlas_package),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::TextureView(output_view),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: params_buffer.as_entire_binding(),
+            },
+        ],
+    })
+}
+
+/// Creates the storage texture the ray-trace compute pass writes shaded
+/// pixels into. Not composited into the swapchain yet -- see `WgpuRenderer`.
+pub fn create_ray_output_texture(ctx: &GraphicsContext, width: u32, height: u32) -> (wgpu::Texture, TextureView) {
+    let texture = ctx.device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("ray_trace_output"),
+        size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[TextureFormat::Rgba8Unorm],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    (texture, view)
+}
+
+/// Builds the compute pipeline that traces shadow rays (and an optional
+/// single reflection bounce) against the TLAS using `rayQuery` in WGSL.
+pub fn ray_trace_pipeline(ctx: &GraphicsContext, bind_group_layout: &BindGroupLayout) -> ComputePipeline {
+    let shader_module = ctx
+        .device
+        .create_shader_module(wgpu::include_wgsl!("assets/raytrace.wgsl"));
+
+    let pipeline_layout = ctx
+        .device
+        .create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("ray_trace_pipeline_layout_descriptor"),
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+    ctx.device.create_compute_pipeline(&ComputePipelineDescriptor {
+        label: Some("ray_trace_pipeline"),
+        layout: Some(&pipeline_layout),
+        module: &shader_module,
+        entry_point: "cs_main",
+        compilation_options: Default::default(),
+        cache: None,
+    })
+}
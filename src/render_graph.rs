@@ -0,0 +1,470 @@
+use std::collections::HashMap;
+
+use wgpu::{
+    Color, CommandEncoder, Extent3d, LoadOp, Operations, RenderPass, RenderPassColorAttachment,
+    RenderPassDepthStencilAttachment, RenderPassDescriptor, StoreOp, Texture, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureUsages, TextureView, TextureViewDescriptor,
+};
+
+use crate::graphics_context::GraphicsContext;
+
+/// Handle to a texture owned by a `RenderGraph`. Resolved to a concrete
+/// `wgpu::Texture` only once the graph is compiled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureHandle(usize);
+
+#[derive(Debug, Clone)]
+pub struct TextureDesc {
+    pub label: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub format: TextureFormat,
+    /// Must match the sample count of any other attachment a pass using this
+    /// texture also binds (wgpu requires every attachment in a render pass
+    /// to share one sample count). 1 for a non-multisampled texture.
+    pub sample_count: u32,
+}
+
+/// Either a graph-owned transient texture, or an externally supplied one
+/// (e.g. the swapchain's current frame), imported so passes can depend on
+/// it like any other resource.
+enum Resource {
+    Transient(TextureDesc),
+    Imported(TextureView),
+}
+
+struct PassNode {
+    name: &'static str,
+    reads: Vec<TextureHandle>,
+    color_attachments: Vec<(TextureHandle, Option<TextureHandle>, Operations<Color>)>,
+    depth_attachment: Option<(TextureHandle, Operations<f32>)>,
+    execute: Box<dyn FnOnce(&mut RenderPass, &HashMap<TextureHandle, &TextureView>)>,
+}
+
+/// Builds a frame's worth of render passes as a dependency graph: each pass
+/// declares the textures it reads and writes, the graph topologically
+/// sorts passes so writers run before readers, allocates (and aliases)
+/// the transient textures those passes need, and drives `wgpu`'s render
+/// pass recording directly from that sorted order and declared attachments.
+///
+/// Because every pass's resource usage is known up front, the backing
+/// textures are created with the union of usages every pass actually needs
+/// (`RENDER_ATTACHMENT` for writers, `TEXTURE_BINDING` for readers) instead
+/// of each pass guessing at the right flags inline.
+#[derive(Default)]
+pub struct RenderGraph {
+    resources: Vec<Resource>,
+    passes: Vec<PassNode>,
+}
+
+pub struct PassBuilder<'g> {
+    graph: &'g mut RenderGraph,
+    name: &'static str,
+    reads: Vec<TextureHandle>,
+    color_attachments: Vec<(TextureHandle, Option<TextureHandle>, Operations<Color>)>,
+    depth_attachment: Option<(TextureHandle, Operations<f32>)>,
+}
+
+impl RenderGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares a transient texture the graph will allocate when compiled.
+    pub fn create_texture(&mut self, desc: TextureDesc) -> TextureHandle {
+        self.resources.push(Resource::Transient(desc));
+        TextureHandle(self.resources.len() - 1)
+    }
+
+    /// Imports an externally owned view (e.g. the swapchain frame) as a graph resource.
+    pub fn import_texture(&mut self, view: TextureView) -> TextureHandle {
+        self.resources.push(Resource::Imported(view));
+        TextureHandle(self.resources.len() - 1)
+    }
+
+    pub fn add_pass(&mut self, name: &'static str) -> PassBuilder<'_> {
+        PassBuilder {
+            graph: self,
+            name,
+            reads: vec![],
+            color_attachments: vec![],
+            depth_attachment: None,
+        }
+    }
+
+    /// Topologically sorts the registered passes, allocates transient
+    /// textures, and records every pass's render pass in dependency order.
+    pub fn execute(self, ctx: &GraphicsContext, encoder: &mut CommandEncoder) -> anyhow::Result<()> {
+        let order = self.topological_order()?;
+        // Kept alive until `execute` returns; `textures` only borrows views out of it.
+        let (_physical_textures, textures) = self.allocate_transients(ctx, &order);
+
+        let mut views = HashMap::new();
+        for (handle, resource) in self.resources.iter().enumerate() {
+            let handle = TextureHandle(handle);
+            match resource {
+                Resource::Transient(_) => {
+                    views.insert(handle, &textures[&handle]);
+                }
+                Resource::Imported(view) => {
+                    views.insert(handle, view);
+                }
+            }
+        }
+
+        let mut passes: Vec<Option<PassNode>> = self.passes.into_iter().map(Some).collect();
+        for index in order {
+            let pass = passes[index].take().expect("pass visited twice");
+
+            let color_attachments = pass
+                .color_attachments
+                .iter()
+                .map(|(handle, resolve, ops)| {
+                    Some(RenderPassColorAttachment {
+                        view: views[handle],
+                        resolve_target: resolve.map(|resolve| views[&resolve]),
+                        ops: *ops,
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let depth_stencil_attachment =
+                pass.depth_attachment.as_ref().map(|(handle, ops)| RenderPassDepthStencilAttachment {
+                    view: views[handle],
+                    depth_ops: Some(*ops),
+                    stencil_ops: None,
+                });
+
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some(pass.name),
+                color_attachments: &color_attachments,
+                depth_stencil_attachment,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            (pass.execute)(&mut render_pass, &views);
+        }
+
+        Ok(())
+    }
+
+    /// Depth-first topological sort over the read-after-write dependency edges.
+    fn topological_order(&self) -> anyhow::Result<Vec<usize>> {
+        let mut writers: HashMap<TextureHandle, usize> = HashMap::new();
+        for (index, pass) in self.passes.iter().enumerate() {
+            for (handle, _, _) in &pass.color_attachments {
+                writers.insert(*handle, index);
+            }
+            if let Some((handle, _)) = &pass.depth_attachment {
+                writers.insert(*handle, index);
+            }
+        }
+
+        let mut dependencies = vec![vec![]; self.passes.len()];
+        for (index, pass) in self.passes.iter().enumerate() {
+            for read in &pass.reads {
+                if let Some(&writer) = writers.get(read) {
+                    if writer != index {
+                        dependencies[index].push(writer);
+                    }
+                }
+            }
+        }
+
+        let mut visited = vec![false; self.passes.len()];
+        let mut visiting = vec![false; self.passes.len()];
+        let mut order = vec![];
+
+        fn visit(
+            index: usize,
+            dependencies: &[Vec<usize>],
+            visited: &mut [bool],
+            visiting: &mut [bool],
+            order: &mut Vec<usize>,
+        ) -> anyhow::Result<()> {
+            if visited[index] {
+                return Ok(());
+            }
+            if visiting[index] {
+                anyhow::bail!("render graph has a cyclic pass dependency");
+            }
+
+            visiting[index] = true;
+            for &dependency in &dependencies[index] {
+                visit(dependency, dependencies, visited, visiting, order)?;
+            }
+            visiting[index] = false;
+            visited[index] = true;
+            order.push(index);
+
+            Ok(())
+        }
+
+        for index in 0..self.passes.len() {
+            visit(index, &dependencies, &mut visited, &mut visiting, &mut order)?;
+        }
+
+        Ok(order)
+    }
+
+    /// Creates the backing texture for each transient resource, aliasing
+    /// identical descriptors whose lifetimes (first use to last use, in pass
+    /// execution order) don't overlap so the graph doesn't allocate one
+    /// physical texture per pass.
+    ///
+    /// Returns the physical textures (which the caller must keep alive for
+    /// as long as the views are in use) alongside each handle's view, which
+    /// may share a physical texture with other handles.
+    fn allocate_transients(
+        &self,
+        ctx: &GraphicsContext,
+        order: &[usize],
+    ) -> (Vec<Texture>, HashMap<TextureHandle, TextureView>) {
+        let mut usage_by_handle: HashMap<TextureHandle, TextureUsages> = HashMap::new();
+        for pass in &self.passes {
+            for &handle in &pass.reads {
+                *usage_by_handle.entry(handle).or_default() |= TextureUsages::TEXTURE_BINDING;
+            }
+            for &(handle, resolve, _) in &pass.color_attachments {
+                *usage_by_handle.entry(handle).or_default() |= TextureUsages::RENDER_ATTACHMENT;
+                if let Some(resolve) = resolve {
+                    *usage_by_handle.entry(resolve).or_default() |= TextureUsages::RENDER_ATTACHMENT;
+                }
+            }
+            if let Some((handle, _)) = pass.depth_attachment {
+                *usage_by_handle.entry(handle).or_default() |= TextureUsages::RENDER_ATTACHMENT;
+            }
+        }
+
+        // The first and last position in `order` at which each resource is read or written.
+        let mut lifetime_by_handle: HashMap<TextureHandle, (usize, usize)> = HashMap::new();
+        for (position, &pass_index) in order.iter().enumerate() {
+            let pass = &self.passes[pass_index];
+            let touched = pass
+                .reads
+                .iter()
+                .copied()
+                .chain(pass.color_attachments.iter().flat_map(|&(h, resolve, _)| {
+                    std::iter::once(h).chain(resolve)
+                }))
+                .chain(pass.depth_attachment.map(|(h, _)| h));
+
+            for handle in touched {
+                let lifetime = lifetime_by_handle.entry(handle).or_insert((position, position));
+                lifetime.1 = lifetime.1.max(position);
+            }
+        }
+
+        let mut transients: Vec<(TextureHandle, &TextureDesc)> = self
+            .resources
+            .iter()
+            .enumerate()
+            .filter_map(|(index, resource)| match resource {
+                Resource::Transient(desc) => Some((TextureHandle(index), desc)),
+                Resource::Imported(_) => None,
+            })
+            .collect();
+        // Hand out physical slots in the order resources first come alive, so a
+        // slot retired by an earlier resource is available to the next one that needs it.
+        transients.sort_by_key(|(handle, _)| lifetime_by_handle.get(handle).copied().unwrap_or_default().0);
+
+        // A physical texture slot, identified by its descriptor, and the last
+        // lifetime position any resource aliased into it is still live.
+        struct Slot {
+            key: (u32, u32, TextureFormat, u32),
+            end: usize,
+            handles: Vec<TextureHandle>,
+        }
+
+        let mut slots: Vec<Slot> = vec![];
+        let mut slot_by_handle: HashMap<TextureHandle, usize> = HashMap::new();
+
+        for (handle, desc) in &transients {
+            let (start, end) = lifetime_by_handle.get(handle).copied().unwrap_or((0, 0));
+            let key = (desc.width, desc.height, desc.format, desc.sample_count);
+
+            let slot_index = slots
+                .iter()
+                .position(|slot| slot.key == key && slot.end < start)
+                .unwrap_or_else(|| {
+                    slots.push(Slot { key, end: 0, handles: vec![] });
+                    slots.len() - 1
+                });
+
+            let slot = &mut slots[slot_index];
+            slot.end = end;
+            slot.handles.push(*handle);
+            slot_by_handle.insert(*handle, slot_index);
+        }
+
+        let labels: HashMap<TextureHandle, &'static str> =
+            transients.iter().map(|&(h, d)| (h, d.label)).collect();
+
+        let physical_textures: Vec<Texture> = slots
+            .iter()
+            .map(|slot| {
+                let usage = slot
+                    .handles
+                    .iter()
+                    .filter_map(|handle| usage_by_handle.get(handle).copied())
+                    .fold(TextureUsages::empty(), |acc, usage| acc | usage);
+
+                ctx.device.create_texture(&TextureDescriptor {
+                    label: slot.handles.first().and_then(|h| labels.get(h)).copied(),
+                    size: Extent3d {
+                        width: slot.key.0,
+                        height: slot.key.1,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: slot.key.3,
+                    dimension: TextureDimension::D2,
+                    format: slot.key.2,
+                    usage,
+                    view_formats: &[slot.key.2],
+                })
+            })
+            .collect();
+
+        let views = slot_by_handle
+            .into_iter()
+            .map(|(handle, slot_index)| {
+                let view = physical_textures[slot_index].create_view(&TextureViewDescriptor::default());
+                (handle, view)
+            })
+            .collect();
+
+        (physical_textures, views)
+    }
+}
+
+impl<'g> PassBuilder<'g> {
+    /// Declares that this pass samples from `handle` (e.g. a shadow map written by an earlier pass).
+    pub fn reads(mut self, handle: TextureHandle) -> Self {
+        self.reads.push(handle);
+        self
+    }
+
+    pub fn color_attachment(mut self, handle: TextureHandle, ops: Operations<Color>) -> Self {
+        self.color_attachments.push((handle, None, ops));
+        self
+    }
+
+    /// Like `color_attachment`, but resolves the (presumably multisampled)
+    /// `handle` into `resolve` (e.g. the swapchain view) at the end of the pass.
+    pub fn color_attachment_resolve(
+        mut self,
+        handle: TextureHandle,
+        resolve: TextureHandle,
+        ops: Operations<Color>,
+    ) -> Self {
+        self.color_attachments.push((handle, Some(resolve), ops));
+        self
+    }
+
+    pub fn depth_attachment(mut self, handle: TextureHandle, ops: Operations<f32>) -> Self {
+        self.depth_attachment = Some((handle, ops));
+        self
+    }
+
+    /// Finalizes the pass with the closure that records its draw calls.
+    pub fn execute(
+        self,
+        execute: impl FnOnce(&mut RenderPass, &HashMap<TextureHandle, &TextureView>) + 'static,
+    ) {
+        self.graph.passes.push(PassNode {
+            name: self.name,
+            reads: self.reads,
+            color_attachments: self.color_attachments,
+            depth_attachment: self.depth_attachment,
+            execute: Box::new(execute),
+        });
+    }
+}
+
+pub const CLEAR_COLOR: Operations<Color> = Operations {
+    load: LoadOp::Clear(Color::BLACK),
+    store: StoreOp::Store,
+};
+
+/// Clears to `0.0`, pairing with reversed-Z depth tests
+/// (`CompareFunction::GreaterEqual`, as used by `mesh_pipeline`'s forward
+/// pass). Do not use this for a pass whose depth test is `LessEqual` —
+/// see `CLEAR_SHADOW_DEPTH`.
+pub const CLEAR_DEPTH: Operations<f32> = Operations {
+    load: LoadOp::Clear(0.0),
+    store: StoreOp::Store,
+};
+
+/// Clears to `1.0`, pairing with standard (non-reversed) depth tests
+/// (`CompareFunction::LessEqual`, as used by `shadow_pipeline`). Using
+/// `CLEAR_DEPTH` here would clear the shadow map to `0.0` and fail almost
+/// every `LessEqual` comparison, producing an effectively empty
+/// (fully-lit) shadow map.
+pub const CLEAR_SHADOW_DEPTH: Operations<f32> = Operations {
+    load: LoadOp::Clear(1.0),
+    store: StoreOp::Store,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESC: TextureDesc = TextureDesc {
+        label: "test",
+        width: 1,
+        height: 1,
+        format: TextureFormat::Rgba8UnormSrgb,
+        sample_count: 1,
+    };
+
+    #[test]
+    fn orders_a_writer_before_its_reader() {
+        let mut graph = RenderGraph::new();
+        let texture = graph.create_texture(DESC);
+
+        graph.add_pass("reader").reads(texture).execute(|_, _| {});
+        graph
+            .add_pass("writer")
+            .color_attachment(texture, CLEAR_COLOR)
+            .execute(|_, _| {});
+
+        let order = graph.topological_order().unwrap();
+
+        // "writer" was registered second (index 1) but must run before "reader" (index 0).
+        assert_eq!(order, vec![1, 0]);
+    }
+
+    #[test]
+    fn passes_with_no_shared_resources_keep_registration_order() {
+        let mut graph = RenderGraph::new();
+        let a = graph.create_texture(DESC);
+        let b = graph.create_texture(DESC);
+
+        graph.add_pass("a").color_attachment(a, CLEAR_COLOR).execute(|_, _| {});
+        graph.add_pass("b").color_attachment(b, CLEAR_COLOR).execute(|_, _| {});
+
+        assert_eq!(graph.topological_order().unwrap(), vec![0, 1]);
+    }
+
+    #[test]
+    fn rejects_a_cyclic_dependency() {
+        let mut graph = RenderGraph::new();
+        let a = graph.create_texture(DESC);
+        let b = graph.create_texture(DESC);
+
+        graph
+            .add_pass("a")
+            .reads(b)
+            .color_attachment(a, CLEAR_COLOR)
+            .execute(|_, _| {});
+        graph
+            .add_pass("b")
+            .reads(a)
+            .color_attachment(b, CLEAR_COLOR)
+            .execute(|_, _| {});
+
+        assert!(graph.topological_order().is_err());
+    }
+}
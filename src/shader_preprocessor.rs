@@ -0,0 +1,253 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+
+/// Flattens a WGSL source tree into a single string understood by
+/// `create_shader_module`.
+///
+/// Supports `#include "path.wgsl"`, resolved relative to the including
+/// file's directory, and `#define NAME` / `#ifdef NAME` / `#else` /
+/// `#endif` conditional compilation driven by `defines`. Each file is
+/// spliced in at most once, even if included from multiple places, and
+/// include cycles are rejected.
+pub fn preprocess(entry: impl AsRef<Path>, defines: &HashSet<&str>) -> Result<String> {
+    let mut defines: HashSet<String> = defines.iter().map(|s| s.to_string()).collect();
+    let mut visited = HashSet::new();
+    let mut stack = vec![];
+    let mut out = String::new();
+
+    include_file(entry.as_ref(), &mut defines, &mut visited, &mut stack, &mut out)?;
+
+    Ok(out)
+}
+
+fn include_file(
+    path: &Path,
+    defines: &mut HashSet<String>,
+    visited: &mut HashSet<PathBuf>,
+    stack: &mut Vec<PathBuf>,
+    out: &mut String,
+) -> Result<()> {
+    let canonical = path
+        .canonicalize()
+        .with_context(|| format!("failed to resolve shader include `{}`", path.display()))?;
+
+    if stack.contains(&canonical) {
+        bail!(
+            "cyclic shader include detected: {} -> {}",
+            stack
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" -> "),
+            canonical.display()
+        );
+    }
+
+    // Already spliced in elsewhere in the tree: skip silently, like a C header guard.
+    if !visited.insert(canonical.clone()) {
+        return Ok(());
+    }
+
+    let source = fs::read_to_string(&canonical)
+        .with_context(|| format!("failed to read shader file `{}`", canonical.display()))?;
+    let dir = canonical.parent().unwrap_or_else(|| Path::new("."));
+
+    stack.push(canonical.clone());
+
+    // Tracks whether the current conditional block (and all enclosing ones)
+    // is active, plus whether the current block has already taken a branch
+    // (so `#else` after a taken `#ifdef` stays suppressed).
+    let mut active_stack: Vec<bool> = vec![];
+    let mut taken_stack: Vec<bool> = vec![];
+
+    for line in source.lines() {
+        let trimmed = line.trim();
+
+        if let Some(rest) = trimmed.strip_prefix("#include") {
+            if active_stack.iter().all(|active| *active) {
+                let include_path = parse_quoted(rest.trim())
+                    .with_context(|| format!("malformed #include in `{}`", canonical.display()))?;
+                include_file(&dir.join(include_path), defines, visited, stack, out)?;
+            }
+            continue;
+        }
+
+        if let Some(rest) = trimmed.strip_prefix("#define") {
+            if active_stack.iter().all(|active| *active) {
+                defines.insert(rest.trim().to_owned());
+            }
+            continue;
+        }
+
+        if let Some(rest) = trimmed.strip_prefix("#ifdef") {
+            let condition = defines.contains(rest.trim());
+            active_stack.push(condition);
+            taken_stack.push(condition);
+            continue;
+        }
+
+        if trimmed.starts_with("#else") {
+            let Some(taken) = taken_stack.last_mut() else {
+                bail!("`#else` with no matching `#ifdef` in `{}`", canonical.display());
+            };
+            let branch = !*taken;
+            *taken = true;
+            *active_stack.last_mut().unwrap() = branch;
+            continue;
+        }
+
+        if trimmed.starts_with("#endif") {
+            if active_stack.pop().is_none() || taken_stack.pop().is_none() {
+                bail!("`#endif` with no matching `#ifdef` in `{}`", canonical.display());
+            }
+            continue;
+        }
+
+        if active_stack.iter().all(|active| *active) {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+
+    if !active_stack.is_empty() {
+        bail!("unterminated `#ifdef` in `{}`", canonical.display());
+    }
+
+    stack.pop();
+    Ok(())
+}
+
+fn parse_quoted(s: &str) -> Result<&str> {
+    let s = s.strip_prefix('"').context("expected opening `\"`")?;
+    let s = s.strip_suffix('"').context("expected closing `\"`")?;
+    Ok(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    /// Writes `files` (relative path -> contents) into a fresh scratch
+    /// directory under `std::env::temp_dir()` and returns it, so tests can
+    /// exercise real `#include` resolution without a fixtures directory.
+    fn scratch_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+        let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+
+        let dir = std::env::temp_dir().join(format!(
+            "shader_preprocessor_test_{}_{}_{}",
+            std::process::id(),
+            name,
+            id
+        ));
+        fs::create_dir_all(&dir).unwrap();
+
+        for (relative_path, contents) in files {
+            let path = dir.join(relative_path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).unwrap();
+            }
+            fs::write(path, contents).unwrap();
+        }
+
+        dir
+    }
+
+    #[test]
+    fn splices_includes_in_place() {
+        let dir = scratch_dir(
+            "includes",
+            &[
+                ("entry.wgsl", "before\n#include \"part.wgsl\"\nafter\n"),
+                ("part.wgsl", "middle\n"),
+            ],
+        );
+
+        let out = preprocess(dir.join("entry.wgsl"), &HashSet::new()).unwrap();
+
+        assert_eq!(out, "before\nmiddle\nafter\n");
+    }
+
+    #[test]
+    fn includes_a_shared_file_only_once() {
+        let dir = scratch_dir(
+            "diamond",
+            &[
+                ("entry.wgsl", "#include \"a.wgsl\"\n#include \"b.wgsl\"\n"),
+                ("a.wgsl", "#include \"shared.wgsl\"\n"),
+                ("b.wgsl", "#include \"shared.wgsl\"\n"),
+                ("shared.wgsl", "shared\n"),
+            ],
+        );
+
+        let out = preprocess(dir.join("entry.wgsl"), &HashSet::new()).unwrap();
+
+        assert_eq!(out, "shared\n");
+    }
+
+    #[test]
+    fn rejects_a_cyclic_include() {
+        let dir = scratch_dir(
+            "cycle",
+            &[
+                ("a.wgsl", "#include \"b.wgsl\"\n"),
+                ("b.wgsl", "#include \"a.wgsl\"\n"),
+            ],
+        );
+
+        let result = preprocess(dir.join("a.wgsl"), &HashSet::new());
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn ifdef_keeps_the_taken_branch_only() {
+        let dir = scratch_dir(
+            "ifdef",
+            &[(
+                "entry.wgsl",
+                "#ifdef FOO\nfoo\n#else\nnot_foo\n#endif\n",
+            )],
+        );
+
+        let mut foo = HashSet::new();
+        foo.insert("FOO");
+        assert_eq!(preprocess(dir.join("entry.wgsl"), &foo).unwrap(), "foo\n");
+
+        assert_eq!(
+            preprocess(dir.join("entry.wgsl"), &HashSet::new()).unwrap(),
+            "not_foo\n"
+        );
+    }
+
+    #[test]
+    fn define_directive_takes_effect_for_the_rest_of_the_file() {
+        let dir = scratch_dir(
+            "define",
+            &[(
+                "entry.wgsl",
+                "#define FOO\n#ifdef FOO\nfoo\n#endif\n",
+            )],
+        );
+
+        let out = preprocess(dir.join("entry.wgsl"), &HashSet::new()).unwrap();
+
+        assert_eq!(out, "foo\n");
+    }
+
+    #[test]
+    fn rejects_an_unterminated_ifdef() {
+        let dir = scratch_dir("unterminated", &[("entry.wgsl", "#ifdef FOO\nfoo\n")]);
+
+        let result = preprocess(dir.join("entry.wgsl"), &HashSet::new());
+
+        assert!(result.is_err());
+    }
+}
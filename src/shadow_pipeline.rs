@@ -0,0 +1,109 @@
+use bytemuck::{Pod, Zeroable};
+use glam::Mat4;
+use wgpu::{
+    vertex_attr_array, BufferAddress, CompareFunction, DepthBiasState, DepthStencilState, Extent3d,
+    FrontFace, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology,
+    PushConstantRange, RenderPipeline, RenderPipelineDescriptor, ShaderStages, StencilState,
+    Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView,
+    TextureViewDescriptor, VertexBufferLayout, VertexState, VertexStepMode,
+};
+
+use crate::graphics_context::GraphicsContext;
+
+pub const SHADOW_MAP_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+/// Scales a `ShadowSettings::depth_bias` (a small light-space NDC depth
+/// offset, e.g. `0.0025`) into the integer units `DepthBiasState::constant`
+/// is specified in for a `Depth32Float` shadow map.
+const DEPTH_BIAS_SCALE: f32 = (1_i32 << 24) as f32;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub struct ShadowPushConstants {
+    /// Model-to-light-clip-space transform for the mesh being rendered.
+    pub light_mvp: Mat4,
+}
+
+/// A depth-only render pipeline that renders the scene from a light's point
+/// of view into a shadow map, biased by `depth_bias` (see
+/// `ShadowSettings::depth_bias`) to avoid acne/peter-panning. Lights with
+/// different biases need their own pipeline instance.
+pub fn shadow_pipeline(ctx: &GraphicsContext, depth_bias: f32) -> RenderPipeline {
+    let shader_module = ctx
+        .device
+        .create_shader_module(wgpu::include_wgsl!("assets/shadow.wgsl"));
+
+    let pipeline_layout = ctx
+        .device
+        .create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("shadow_pipeline_layout_descriptor"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[PushConstantRange {
+                stages: ShaderStages::VERTEX,
+                range: 0..u32::try_from(std::mem::size_of::<ShadowPushConstants>()).unwrap(),
+            }],
+        });
+
+    ctx.device
+        .create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("shadow_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader_module,
+                entry_point: "vs_main",
+                buffers: &[VertexBufferLayout {
+                    array_stride: std::mem::size_of::<[f32; 3]>() as BufferAddress,
+                    step_mode: VertexStepMode::Vertex,
+                    attributes: &vertex_attr_array![0 => Float32x3],
+                }],
+            },
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: FrontFace::Ccw,
+                // Cull front faces instead of back faces to reduce peter-panning.
+                cull_mode: Some(wgpu::Face::Front),
+                unclipped_depth: false,
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: Some(DepthStencilState {
+                format: SHADOW_MAP_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::LessEqual,
+                stencil: StencilState::default(),
+                bias: DepthBiasState {
+                    constant: (depth_bias * DEPTH_BIAS_SCALE) as i32,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: Default::default(),
+            fragment: None,
+            multiview: None,
+        })
+}
+
+/// Creates the depth texture the shadow pass renders into, along with a
+/// single default view that serves both the comparison sampler and the
+/// regular depth sampler.
+pub fn create_shadow_map(ctx: &GraphicsContext, size: u32) -> (Texture, TextureView) {
+    let texture = ctx.device.create_texture(&TextureDescriptor {
+        label: Some("shadow_map"),
+        size: Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: SHADOW_MAP_FORMAT,
+        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        view_formats: &[SHADOW_MAP_FORMAT],
+    });
+
+    let view = texture.create_view(&TextureViewDescriptor::default());
+
+    (texture, view)
+}
@@ -12,7 +12,7 @@ enum StateType {
     String(String),
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct State(HashMap<Cow<'static, str>, StateType>);
 
 impl Asset for State {
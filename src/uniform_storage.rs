@@ -0,0 +1,155 @@
+use std::{marker::PhantomData, mem::size_of};
+
+use wgpu::{Buffer, BufferDescriptor, BufferUsages, Device, Maintain, Queue, SubmissionIndex};
+
+/// A single frame-in-flight's region of the ring buffer: a write cursor and,
+/// once submitted, the submission it must finish before its space can be
+/// reused.
+struct Segment {
+    cursor: u64,
+    submission_index: Option<SubmissionIndex>,
+}
+
+/// Bump-allocates per-draw uniform data (per-object transforms, materials,
+/// etc.) from a large host-visible buffer, sized as N ring segments — one
+/// per frame-in-flight — so writing a new frame's data never races the GPU
+/// still reading a previous frame's segment out of the same buffer.
+///
+/// This complements the context's 128-byte push constant budget, which is
+/// too small for per-object data once a scene has more than a handful of draws.
+pub struct UniformStorage<T> {
+    buffer: Buffer,
+    segment_size: u64,
+    alignment: u64,
+    segments: Vec<Segment>,
+    current: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: bytemuck::Pod> UniformStorage<T> {
+    /// `frames_in_flight` should match `surface_config.desired_maximum_frame_latency`.
+    /// `segment_size` is the initial per-frame capacity in bytes; it grows
+    /// (doubling) the first time a frame's allocations exceed it.
+    pub fn new(device: &Device, frames_in_flight: u32, segment_size: u64) -> Self {
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+
+        Self {
+            buffer: create_buffer(device, segment_size * frames_in_flight as u64),
+            segment_size,
+            alignment,
+            segments: (0..frames_in_flight)
+                .map(|_| Segment {
+                    cursor: 0,
+                    submission_index: None,
+                })
+                .collect(),
+            current: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Advances to the next ring segment, waiting for its previous
+    /// occupant's submission to finish before handing its space back out,
+    /// then resets its write cursor. Call this once at the start of each frame.
+    pub fn begin_frame(&mut self, device: &Device) {
+        self.current = (self.current + 1) % self.segments.len();
+
+        let segment = &mut self.segments[self.current];
+        if let Some(index) = segment.submission_index.take() {
+            device.poll(Maintain::WaitForSubmissionIndex(index));
+        }
+        segment.cursor = 0;
+    }
+
+    /// Records the submission this frame's allocations depend on, so the
+    /// next time this segment comes back around, `begin_frame` knows what to
+    /// wait on before reusing it.
+    pub fn end_frame(&mut self, submission_index: SubmissionIndex) {
+        self.segments[self.current].submission_index = Some(submission_index);
+    }
+
+    /// Bump-allocates space for `value` in the current frame's segment,
+    /// uploads it, and returns the dynamic offset to bind it at. Doubles the
+    /// buffer's per-segment capacity if this frame's segment has overflowed.
+    pub fn allocate(&mut self, device: &Device, queue: &Queue, value: &T) -> u64 {
+        let cursor = self.segments[self.current].cursor;
+        let size = size_of::<T>() as u64;
+
+        let (offset, new_cursor) = bump_allocate(cursor, size, self.alignment, self.segment_size)
+            .or_else(|| {
+                self.grow(device);
+                bump_allocate(0, size, self.alignment, self.segment_size)
+            })
+            .expect("a freshly doubled segment always fits one allocation");
+
+        self.segments[self.current].cursor = new_cursor;
+
+        let global_offset = self.current as u64 * self.segment_size + offset;
+        queue.write_buffer(&self.buffer, global_offset, bytemuck::bytes_of(value));
+
+        global_offset
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    /// Doubles `segment_size` and reallocates the backing buffer. Any bind
+    /// groups referencing the old buffer must be recreated by the caller.
+    ///
+    /// Cursors reset to the start of their (now larger) region in the new
+    /// buffer, but a segment's `submission_index` is left untouched: it still
+    /// tracks GPU work reading the *old* buffer, which `begin_frame` must
+    /// keep waiting on before that segment's turn comes around again.
+    fn grow(&mut self, device: &Device) {
+        self.segment_size *= 2;
+        self.buffer = create_buffer(device, self.segment_size * self.segments.len() as u64);
+        for segment in &mut self.segments {
+            segment.cursor = 0;
+        }
+    }
+}
+
+fn create_buffer(device: &Device, size: u64) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+        label: Some("uniform_storage"),
+        size,
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Rounds `cursor` up to `alignment` and reserves `size` bytes there,
+/// returning the aligned offset to write at and the cursor's new value.
+/// Returns `None` if that would overflow `segment_size`.
+fn bump_allocate(cursor: u64, size: u64, alignment: u64, segment_size: u64) -> Option<(u64, u64)> {
+    let offset = cursor.next_multiple_of(alignment);
+    let new_cursor = offset + size;
+    (new_cursor <= segment_size).then_some((offset, new_cursor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::bump_allocate;
+
+    #[test]
+    fn packs_allocations_without_alignment_padding() {
+        assert_eq!(bump_allocate(0, 64, 64, 256), Some((0, 64)));
+        assert_eq!(bump_allocate(64, 64, 64, 256), Some((64, 128)));
+    }
+
+    #[test]
+    fn rounds_the_offset_up_to_alignment() {
+        assert_eq!(bump_allocate(48, 64, 64, 256), Some((64, 128)));
+    }
+
+    #[test]
+    fn rejects_an_allocation_that_would_overflow_the_segment() {
+        assert_eq!(bump_allocate(192, 128, 64, 256), None);
+    }
+
+    #[test]
+    fn accepts_an_allocation_that_exactly_fills_the_segment() {
+        assert_eq!(bump_allocate(0, 256, 64, 256), Some((0, 256)));
+    }
+}
@@ -0,0 +1,494 @@
+//! The wgpu-backed alternative to `GraphicsSubsystem`, selected with
+//! `--backend wgpu`. Where the vulkano backend drives its swapchain from a
+//! dedicated render thread, `WgpuRenderer` renders synchronously on the
+//! winit thread, so `Engine::run` must explicitly request a redraw every
+//! frame to keep it animating (see its `AboutToWait` arm).
+
+use std::{
+    collections::HashSet,
+    mem::size_of,
+    num::NonZeroU64,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use glam::{Mat4, Vec3};
+use tracing::{error, info, warn};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, Blas, Buffer, BufferUsages,
+    CommandEncoderDescriptor, CompareFunction, FilterMode, IndexFormat, Maintain, SamplerDescriptor, ShaderStages,
+    Texture, TextureFormat, TextureView, TlasPackage,
+};
+use winit::{dpi::PhysicalSize, window::Window};
+
+use crate::{
+    compute_pipeline::{self, Particle, ParticleSystem},
+    graphics_context::{Frame, GraphicsContext, GraphicsContextConfig},
+    light::{Light, ShadowParamsUniform},
+    material::{self, Material},
+    mesh_pipeline,
+    ray_pipeline::{self, RayParamsUniform, RendererKind},
+    render_graph::{self, RenderGraph, TextureDesc},
+    shadow_pipeline,
+    uniform_storage::UniformStorage,
+};
+
+/// Resolution of the directional light's shadow map.
+const SHADOW_MAP_SIZE: u32 = 2048;
+
+/// How many particles `WgpuRenderer` seeds `ParticleSystem` with.
+const PARTICLE_COUNT: usize = 24;
+
+/// Places particles evenly around a ring, each drifting upward at a slightly
+/// different rate, so `ParticleSystem::step` has something visible to do.
+fn initial_particles() -> Vec<Particle> {
+    (0..PARTICLE_COUNT)
+        .map(|i| {
+            let angle = (i as f32 / PARTICLE_COUNT as f32) * std::f32::consts::TAU;
+            let radius = 2.5;
+            Particle {
+                position: [angle.cos() * radius, 0.0, angle.sin() * radius],
+                velocity: [0.0, 0.4 + 0.1 * (i % 5) as f32, 0.0],
+            }
+        })
+        .collect()
+}
+
+/// A single upward-facing triangle, built directly instead of via
+/// `Mesh::load` since there's no glTF asset in this tree to load — this is
+/// enough geometry to prove the shadow and forward passes actually draw.
+struct TriangleMesh {
+    positions: Buffer,
+    normals: Buffer,
+    uvs: Buffer,
+    colors: Buffer,
+    indices: Buffer,
+}
+
+impl TriangleMesh {
+    fn new(gfx: &GraphicsContext) -> Self {
+        let positions: [[f32; 3]; 3] = [[-1.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.5]];
+        let normals: [[f32; 3]; 3] = [[0.0, 1.0, 0.0]; 3];
+        let uvs: [[f32; 2]; 3] = [[0.0, 0.0], [1.0, 0.0], [0.5, 1.0]];
+        let colors: [[f32; 3]; 3] = [[1.0, 1.0, 1.0]; 3];
+        let indices: [u32; 3] = [0, 1, 2];
+
+        Self {
+            positions: gfx.device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("triangle_positions"),
+                contents: bytemuck::cast_slice(&positions),
+                usage: BufferUsages::VERTEX,
+            }),
+            normals: gfx.device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("triangle_normals"),
+                contents: bytemuck::cast_slice(&normals),
+                usage: BufferUsages::VERTEX,
+            }),
+            uvs: gfx.device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("triangle_uvs"),
+                contents: bytemuck::cast_slice(&uvs),
+                usage: BufferUsages::VERTEX,
+            }),
+            colors: gfx.device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("triangle_colors"),
+                contents: bytemuck::cast_slice(&colors),
+                usage: BufferUsages::VERTEX,
+            }),
+            indices: gfx.device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("triangle_indices"),
+                contents: bytemuck::cast_slice(&indices),
+                usage: BufferUsages::INDEX,
+            }),
+        }
+    }
+}
+
+/// Owns the ray-traced path's acceleration structures, output texture, and
+/// compute pipeline. Only built when `--renderer ray-traced` was requested
+/// and the adapter actually supports `ray_pipeline::REQUIRED_FEATURES`.
+///
+/// Its `output_texture` is not composited onto the visible frame: there's no
+/// blit/present path wired up for it yet, so running with this active proves
+/// the BLAS/TLAS build and the ray-query dispatch execute without error, but
+/// doesn't change what's on screen. That's the next step, not this one.
+struct RayTracer {
+    _blas: Blas,
+    _tlas_package: TlasPackage,
+    pipeline: wgpu::ComputePipeline,
+    bind_group: BindGroup,
+    output_texture: Texture,
+}
+
+impl RayTracer {
+    /// Builds a BLAS from `mesh`'s single triangle, a TLAS with one identity-transformed
+    /// instance of it, and the compute pipeline/bind group that traces against it. Blocks
+    /// on the acceleration structure builds finishing (`GraphicsContext::read_texture`
+    /// waits on the device the same way).
+    fn new(gfx: &GraphicsContext, mesh: &TriangleMesh, light: &Light) -> Self {
+        let (blas_desc, size_desc) = ray_pipeline::create_blas(3, 3);
+        let blas = gfx.device.create_blas(
+            &blas_desc,
+            wgpu::BlasGeometrySizeDescriptors::Triangles { desc: vec![size_desc.clone()] },
+        );
+
+        let tlas_package = ray_pipeline::build_tlas(gfx, &blas, &[Mat4::IDENTITY]);
+
+        let mut encoder = gfx
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor { label: Some("ray_trace_setup") });
+        ray_pipeline::record_blas_build(&mut encoder, &blas, &size_desc, &mesh.positions, &mesh.indices);
+        ray_pipeline::record_tlas_build(&mut encoder, &tlas_package);
+        gfx.queue.submit([encoder.finish()]);
+        gfx.device.poll(Maintain::Wait);
+
+        let (output_texture, output_view) =
+            ray_pipeline::create_ray_output_texture(gfx, gfx.surface_config.width, gfx.surface_config.height);
+
+        let params_buffer = gfx.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("ray_trace_params"),
+            contents: bytemuck::bytes_of(&RayParamsUniform {
+                light_direction: light.direction.normalize().to_array(),
+                reflections_enabled: 1,
+            }),
+            usage: BufferUsages::UNIFORM,
+        });
+
+        let bind_group_layout = ray_pipeline::ray_trace_bind_group_layout(gfx);
+        let pipeline = ray_pipeline::ray_trace_pipeline(gfx, &bind_group_layout);
+        let bind_group = ray_pipeline::build_ray_trace_bind_group(
+            gfx,
+            &bind_group_layout,
+            &tlas_package,
+            &output_view,
+            &params_buffer,
+        );
+
+        Self {
+            _blas: blas,
+            _tlas_package: tlas_package,
+            pipeline,
+            bind_group,
+            output_texture,
+        }
+    }
+
+    /// Dispatches one ray-query pass over `output_texture`, one workgroup
+    /// invocation per pixel (matching `assets/raytrace.wgsl`'s 8x8 workgroup size).
+    fn dispatch(&self, encoder: &mut wgpu::CommandEncoder) {
+        let size = self.output_texture.size();
+        compute_pipeline::dispatch(
+            encoder,
+            "ray_trace",
+            &self.pipeline,
+            &[&self.bind_group],
+            None,
+            [size.width.div_ceil(8), size.height.div_ceil(8), 1],
+        );
+    }
+}
+
+pub struct WgpuRenderer {
+    gfx: GraphicsContext<'static>,
+    light: Light,
+    shadow_pipeline: wgpu::RenderPipeline,
+    shadow_bind_group: BindGroup,
+    model_pipeline: wgpu::RenderPipeline,
+    material: Material,
+    mesh: TriangleMesh,
+    /// Owned separately from any per-frame `RenderGraph`: `shadow_bind_group`
+    /// above samples this same texture, so it has to survive across frames
+    /// rather than being reallocated by the graph every time.
+    shadow_map: wgpu::Texture,
+    particles: ParticleSystem,
+    particle_draw_pipeline: wgpu::RenderPipeline,
+    shadow_params: UniformStorage<ShadowParamsUniform>,
+    ray_tracer: Option<RayTracer>,
+    active_renderer: RendererKind,
+    /// Set by `request_screenshot`; consumed at the end of the next `render`
+    /// call, right before the frame it names gets presented.
+    pending_screenshot: Option<PathBuf>,
+}
+
+impl WgpuRenderer {
+    pub fn new(
+        window: Arc<Window>,
+        physical_size: PhysicalSize<u32>,
+        renderer: RendererKind,
+    ) -> anyhow::Result<Self> {
+        let gfx = GraphicsContext::new(window, physical_size, GraphicsContextConfig::default())?;
+
+        let active_renderer = match renderer {
+            RendererKind::Raster => RendererKind::Raster,
+            RendererKind::RayTraced if gfx.ray_tracing_supported => RendererKind::RayTraced,
+            RendererKind::RayTraced => {
+                warn!(
+                    "ray-traced renderer requested but the adapter lacks {:?}; falling back to raster",
+                    ray_pipeline::REQUIRED_FEATURES
+                );
+                RendererKind::Raster
+            }
+        };
+
+        let light = Light {
+            direction: Vec3::new(-0.4, -1.0, -0.3),
+            color: Vec3::ONE,
+            shadow: Default::default(),
+        };
+
+        let (shadow_map, shadow_map_view) = shadow_pipeline::create_shadow_map(&gfx, SHADOW_MAP_SIZE);
+        let shadow_pipeline = shadow_pipeline::shadow_pipeline(&gfx, light.shadow.depth_bias);
+
+        let shadow_params = UniformStorage::new(&gfx.device, gfx.surface_config.desired_maximum_frame_latency, 256);
+
+        let shadow_bind_group_layout = mesh_pipeline::shadow_bind_group_layout(&gfx);
+        let shadow_bind_group =
+            build_shadow_bind_group(&gfx, &shadow_bind_group_layout, &shadow_map_view, shadow_params.buffer());
+
+        let material_bind_group_layout = material::material_bind_group_layout(&gfx);
+        let flags: HashSet<&str> = HashSet::from(["SHADOWS_ENABLED"]);
+        let model_pipeline =
+            mesh_pipeline::model_pipeline(&gfx, &shadow_bind_group_layout, &material_bind_group_layout, &flags)?;
+
+        let material = material::load_material(&gfx, &material_bind_group_layout, [1.0, 1.0, 1.0, 1.0], None);
+        let mesh = TriangleMesh::new(&gfx);
+
+        let particles = ParticleSystem::new(&gfx, &initial_particles());
+        let particle_draw_pipeline = compute_pipeline::particle_draw_pipeline(&gfx);
+
+        let ray_tracer = (active_renderer == RendererKind::RayTraced).then(|| RayTracer::new(&gfx, &mesh, &light));
+
+        Ok(Self {
+            gfx,
+            light,
+            shadow_pipeline,
+            shadow_bind_group,
+            model_pipeline,
+            material,
+            mesh,
+            shadow_map,
+            particles,
+            particle_draw_pipeline,
+            shadow_params,
+            ray_tracer,
+            active_renderer,
+            pending_screenshot: None,
+        })
+    }
+
+    pub fn active_renderer(&self) -> RendererKind {
+        self.active_renderer
+    }
+
+    pub fn resize(&mut self, physical_size: PhysicalSize<u32>) {
+        self.gfx.resize(physical_size);
+    }
+
+    /// Queues a screenshot of the next frame's presented swapchain image,
+    /// saved as a PNG to `path`. Decouples capture from the presented
+    /// surface the same way `GraphicsContext::create_texture_target` does
+    /// for render-to-texture: the read-back goes through the same
+    /// `GraphicsContext::read_texture` staging-buffer path, just aimed at
+    /// the swapchain's texture instead of an owned one.
+    pub fn request_screenshot(&mut self, path: impl Into<PathBuf>) {
+        self.pending_screenshot = Some(path.into());
+    }
+
+    /// Builds and executes a frame as a `RenderGraph`: a shadow pass renders
+    /// the scene from the light's point of view, then a forward pass draws
+    /// it again, sampling that shadow map, into the (possibly multisampled)
+    /// swapchain target.
+    pub fn render(&mut self, window: &Window, view_projection: Mat4, dt: f32) -> anyhow::Result<()> {
+        let Some(frame) = self.gfx.get_frame() else {
+            return Ok(());
+        };
+
+        let model = Mat4::IDENTITY;
+        let light_mvp = self.light.view_projection(Vec3::ZERO, 5.0) * model;
+        let mvp = view_projection * model;
+
+        self.shadow_params.begin_frame(&self.gfx.device);
+        let shadow_params_offset =
+            self.shadow_params.allocate(&self.gfx.device, &self.gfx.queue, &self.light.shadow_params()) as u32;
+
+        let mut encoder = self
+            .gfx
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor { label: Some("frame") });
+
+        self.particles.step(&mut encoder, dt);
+
+        if let Some(ray_tracer) = &self.ray_tracer {
+            ray_tracer.dispatch(&mut encoder);
+        }
+
+        let mut graph = RenderGraph::new();
+
+        let Frame { surface_texture, view, resolve_target } = frame;
+
+        // Reuses the persistent `shadow_map` texture so `shadow_bind_group`, built once at
+        // construction time against it, keeps sampling whatever this pass just wrote.
+        let shadow_target = graph.import_texture(
+            self.shadow_map.create_view(&wgpu::TextureViewDescriptor::default()),
+        );
+        let swapchain = graph.import_texture(view);
+        let resolve_target = resolve_target.map(|view| graph.import_texture(view));
+
+        let depth = graph.create_texture(TextureDesc {
+            label: "depth",
+            width: self.gfx.surface_config.width,
+            height: self.gfx.surface_config.height,
+            format: TextureFormat::Depth32Float,
+            sample_count: self.gfx.sample_count,
+        });
+
+        let shadow_pipeline = &self.shadow_pipeline;
+        let mesh = &self.mesh;
+        graph
+            .add_pass("shadow")
+            .depth_attachment(shadow_target, render_graph::CLEAR_SHADOW_DEPTH)
+            .execute(move |pass, _| {
+                pass.set_pipeline(shadow_pipeline);
+                pass.set_push_constants(
+                    ShaderStages::VERTEX,
+                    0,
+                    bytemuck::bytes_of(&shadow_pipeline::ShadowPushConstants { light_mvp }),
+                );
+                pass.set_vertex_buffer(0, mesh.positions.slice(..));
+                pass.set_index_buffer(mesh.indices.slice(..), IndexFormat::Uint32);
+                pass.draw_indexed(0..3, 0, 0..1);
+            });
+
+        let model_pipeline = &self.model_pipeline;
+        let shadow_bind_group = &self.shadow_bind_group;
+        let material_bind_group = &self.material.bind_group;
+        let particle_draw_pipeline = &self.particle_draw_pipeline;
+        let particle_buffer = &self.particles.buffer;
+        let particle_count = self.particles.particle_count();
+        let mut forward_pass = graph.add_pass("forward").reads(shadow_target);
+        forward_pass = match resolve_target {
+            Some(resolve_target) => forward_pass.color_attachment_resolve(swapchain, resolve_target, render_graph::CLEAR_COLOR),
+            None => forward_pass.color_attachment(swapchain, render_graph::CLEAR_COLOR),
+        };
+        forward_pass.depth_attachment(depth, render_graph::CLEAR_DEPTH).execute(move |pass, _| {
+            pass.set_pipeline(model_pipeline);
+            pass.set_push_constants(
+                ShaderStages::VERTEX,
+                0,
+                bytemuck::bytes_of(&mesh_pipeline::PushConstants { mvp, light_mvp }),
+            );
+            pass.set_bind_group(0, shadow_bind_group, &[shadow_params_offset]);
+            pass.set_bind_group(1, material_bind_group, &[]);
+            pass.set_vertex_buffer(0, mesh.positions.slice(..));
+            pass.set_vertex_buffer(1, mesh.colors.slice(..));
+            pass.set_vertex_buffer(2, mesh.normals.slice(..));
+            pass.set_vertex_buffer(3, mesh.uvs.slice(..));
+            pass.set_index_buffer(mesh.indices.slice(..), IndexFormat::Uint32);
+            pass.draw_indexed(0..3, 0, 0..1);
+
+            // The particle buffer `self.particles.step` just advanced this frame,
+            // drawn straight through with no intermediate copy.
+            pass.set_pipeline(particle_draw_pipeline);
+            pass.set_push_constants(ShaderStages::VERTEX, 0, bytemuck::bytes_of(&view_projection));
+            pass.set_vertex_buffer(0, particle_buffer.slice(..));
+            pass.draw(0..1, 0..particle_count);
+        });
+
+        graph.execute(&self.gfx, &mut encoder)?;
+
+        // Submitted directly through `queue` (rather than `GraphicsContext::submit`) to get
+        // back the `SubmissionIndex` `shadow_params`'s ring buffer needs to know when this
+        // frame's segment is safe to reuse.
+        let submission_index = self.gfx.queue.submit([encoder.finish()]);
+        self.shadow_params.end_frame(submission_index);
+
+        if let Some(path) = self.pending_screenshot.take() {
+            let width = self.gfx.surface_config.width;
+            let height = self.gfx.surface_config.height;
+            let format = self.gfx.surface_format;
+            match self
+                .gfx
+                .read_texture(&surface_texture.texture, format, width, height)
+                .and_then(|pixels| save_screenshot(&path, pixels, width, height, format))
+            {
+                Ok(()) => info!(path = %path.display(), "saved screenshot"),
+                Err(err) => error!(%err, path = %path.display(), "failed to save screenshot"),
+            }
+        }
+
+        window.pre_present_notify();
+        surface_texture.present();
+
+        Ok(())
+    }
+}
+
+/// Saves `pixels` (tightly packed, in `format`'s native channel order) as a
+/// PNG at `path`. The swapchain format is usually BGRA, which `image` has no
+/// `ColorType` for, so the B/R channels are swapped first when that's the case.
+fn save_screenshot(path: &Path, mut pixels: Vec<u8>, width: u32, height: u32, format: TextureFormat) -> anyhow::Result<()> {
+    if matches!(format, TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb) {
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+
+    image::save_buffer(path, &pixels, width, height, image::ColorType::Rgba8)?;
+    Ok(())
+}
+
+/// Builds the bind group `mesh_pipeline::shadow_bind_group_layout` describes:
+/// the shadow map plus its comparison/non-filtering samplers and filter
+/// parameters. Binding 3 is bound against `shadow_params_buffer` (a
+/// `UniformStorage<ShadowParamsUniform>`'s backing buffer) at a fixed
+/// base offset/size; the actual per-frame allocation is selected at draw
+/// time via a dynamic offset, so this bind group is built once and reused.
+fn build_shadow_bind_group(
+    gfx: &GraphicsContext,
+    layout: &BindGroupLayout,
+    shadow_map_view: &TextureView,
+    shadow_params_buffer: &Buffer,
+) -> BindGroup {
+    let comparison_sampler = gfx.device.create_sampler(&SamplerDescriptor {
+        address_mode_u: AddressMode::ClampToEdge,
+        address_mode_v: AddressMode::ClampToEdge,
+        mag_filter: FilterMode::Linear,
+        min_filter: FilterMode::Linear,
+        compare: Some(CompareFunction::LessEqual),
+        ..Default::default()
+    });
+    let depth_sampler = gfx.device.create_sampler(&SamplerDescriptor {
+        address_mode_u: AddressMode::ClampToEdge,
+        address_mode_v: AddressMode::ClampToEdge,
+        mag_filter: FilterMode::Nearest,
+        min_filter: FilterMode::Nearest,
+        ..Default::default()
+    });
+
+    gfx.device.create_bind_group(&BindGroupDescriptor {
+        label: Some("shadow_bind_group"),
+        layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(shadow_map_view),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&comparison_sampler),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(&depth_sampler),
+            },
+            BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: shadow_params_buffer,
+                    offset: 0,
+                    size: NonZeroU64::new(size_of::<ShadowParamsUniform>() as u64),
+                }),
+            },
+        ],
+    })
+}